@@ -9,7 +9,7 @@ pub enum ApiResponse {
     OtherString { cod: String, message: String },
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct CurrentResponse {
     pub coord: Coord,
     pub weather: Vec<Weather>,
@@ -26,31 +26,31 @@ pub struct CurrentResponse {
     pub name: String,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct Main {
     pub temp: f32,
     pub feels_like: f32,
     pub temp_min: f32,
     pub temp_max: f32,
-    pub pressure: u16,
-    pub humidity: u8,
+    pub pressure: Option<u16>,
+    pub humidity: Option<u8>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct Wind {
     pub speed: f32,
     pub deg: Option<f32>,
     pub gale: Option<f32>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct Clouds {
     pub all: u16,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct Sys {
     pub country: String,
-    pub sunrise: i64,
-    pub sunset: i64,
+    pub sunrise: Option<i64>,
+    pub sunset: Option<i64>,
 }
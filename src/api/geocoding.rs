@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ApiResponse {
+    Success(Vec<Place>),
+    // hack: Openweather API returns some very ugly json
+    OtherInt { cod: u16, message: String },
+    OtherString { cod: String, message: String },
+}
+
+/// A place found by OpenWeather's geocoding API.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+pub struct Place {
+    pub name: String,
+    #[serde(default)]
+    pub local_names: HashMap<String, String>,
+    pub lat: f64,
+    pub lon: f64,
+    pub country: String,
+    pub state: Option<String>,
+}
+
+impl Place {
+    /// The name of the place in a language of the form 'aa_AA', if OpenWeather knows it.
+    pub fn local_name(&self, language: Option<&str>) -> &str {
+        language
+            .map(|l| l.split_once('_').map(|t| t.0).unwrap_or(l))
+            .and_then(|l| self.local_names.get(l))
+            .unwrap_or(&self.name)
+    }
+}
@@ -1,10 +1,11 @@
 use anyhow::*;
 
 pub mod current;
+pub mod geocoding;
 pub mod one_call;
 pub mod pollution;
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct Weather {
     pub id: u16,
     pub main: String,
@@ -12,33 +13,34 @@ pub struct Weather {
     pub icon: String,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct Rain {
-    #[serde(rename(deserialize = "1h"))]
+    #[serde(rename = "1h")]
     pub one_h: Option<f32>,
-    #[serde(rename(deserialize = "3h"))]
+    #[serde(rename = "3h")]
     pub three_h: Option<f32>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct Snow {
-    #[serde(rename(deserialize = "1h"))]
+    #[serde(rename = "1h")]
     pub one_h: Option<f32>,
-    #[serde(rename(deserialize = "3h"))]
+    #[serde(rename = "3h")]
     pub three_h: Option<f32>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct Coord {
     pub lat: f64,
     pub lon: f64,
 }
 
-#[derive(Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct Response {
     current: Option<current::CurrentResponse>,
     forecast: Option<one_call::OneCallResponse>,
     pollution: Option<pollution::PollutionResponse>,
+    location_name: Option<String>,
 }
 
 impl Response {
@@ -46,7 +48,8 @@ impl Response {
         Self {
             current: Some(current),
             forecast: None,
-            pollution: None
+            pollution: None,
+            location_name: None,
         }
     }
 
@@ -55,6 +58,7 @@ impl Response {
             current: None,
             forecast: Some(forecast),
             pollution: None,
+            location_name: None,
         }
     }
 
@@ -63,6 +67,16 @@ impl Response {
             current: None,
             forecast: None,
             pollution: Some(pollution),
+            location_name: None,
+        }
+    }
+
+    pub fn from_location_name(name: String) -> Self {
+        Self {
+            current: None,
+            forecast: None,
+            pollution: None,
+            location_name: Some(name),
         }
     }
 
@@ -70,7 +84,8 @@ impl Response {
         Self {
             current: None,
             forecast: None,
-            pollution: None
+            pollution: None,
+            location_name: None,
         }
     }
 
@@ -84,6 +99,9 @@ impl Response {
         if let Some(f) = other.pollution {
             self.pollution = Some(f);
         }
+        if let Some(n) = other.location_name {
+            self.location_name = Some(n);
+        }
     }
 
     pub fn as_current(&self) -> Result<&current::CurrentResponse> {
@@ -98,6 +116,15 @@ impl Response {
             .ok_or_else(|| anyhow!("internal error: missing forecast api data"))
     }
 
+    /// The name of the location, from the current weather or from reverse geocoding.
+    pub fn location_name(&self) -> Result<&str> {
+        match (&self.current, &self.location_name) {
+            (Some(c), _) => Ok(&c.name),
+            (None, Some(n)) => Ok(n),
+            (None, None) => bail!("internal error: missing location name"),
+        }
+    }
+
     pub fn as_pollution(&self) -> Result<&pollution::PollutionResponse> {
         self.pollution
             .as_ref()
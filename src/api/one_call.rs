@@ -7,7 +7,7 @@ pub enum ApiResponse {
     OtherString { cod: String, message: String },
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct OneCallResponse {
     pub lat: f32,
     pub lon: f32,
@@ -17,18 +17,20 @@ pub struct OneCallResponse {
     pub hourly: Option<Vec<WeatherData>>,
     pub daily: Option<Vec<WeatherData>>,
     pub alerts: Option<Vec<Alert>>,
+    #[serde(default)]
+    pub overview: Option<String>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct WeatherData {
     pub dt: i64,
     pub sunrise: Option<i64>,
     pub sunset: Option<i64>,
     pub temp: Temperature,
     pub feels_like: FeelsLike,
-    pub pressure: u16,
-    pub humidity: u8,
-    pub clouds: u16,
+    pub pressure: Option<u16>,
+    pub humidity: Option<u8>,
+    pub clouds: Option<u16>,
     pub visibility: Option<u16>,
     pub wind_speed: f32,
     pub wind_deg: Option<f32>,
@@ -37,30 +39,32 @@ pub struct WeatherData {
     pub rain: Option<RainResult>,
     pub snow: Option<SnowResult>,
     pub weather: Vec<super::Weather>,
+    #[serde(default)]
+    pub summary: Option<String>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 #[serde(untagged)]
 pub enum RainResult {
     Value(f32),
     Values(super::Rain),
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 #[serde(untagged)]
 pub enum SnowResult {
     Value(f32),
     Values(super::Snow),
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 #[serde(untagged)]
 pub enum Temperature {
     Value(f32),
     Values(TempValues),
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct TempValues {
     pub morn: f32,
     pub day: f32,
@@ -70,14 +74,14 @@ pub struct TempValues {
     pub max: f32,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 #[serde(untagged)]
 pub enum FeelsLike {
     Value(f32),
     Values(FeelsLikeValues),
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct FeelsLikeValues {
     pub morn: f32,
     pub day: f32,
@@ -85,13 +89,18 @@ pub struct FeelsLikeValues {
     pub night: f32,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize)]
+pub struct OverviewResponse {
+    pub weather_overview: String,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct MinutelyForecast {
     pub dt: i64,
     pub precipitation: f32,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct Alert {
     pub sender_name: String,
     pub event: String,
@@ -99,4 +108,8 @@ pub struct Alert {
     pub end: i64,
     pub description: String,
     pub tags: Vec<String>,
+    #[serde(default)]
+    pub severity: Option<String>,
+    #[serde(default)]
+    pub urgency: Option<String>,
 }
@@ -9,33 +9,32 @@ pub enum ApiResponse {
     OtherString { cod: String, message: String },
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct PollutionResponse {
     pub coord: Coord,
     pub list: Vec<PollutionData>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct PollutionData {
     pub dt: i64,
     pub main: AQIndex,
     pub components: Components,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct AQIndex {
-    pub aqi: u16
+    pub aqi: u16,
 }
 
-
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct Components {
-    pub co: f64,
-    pub no: f64,
-    pub no2: f64,
-    pub o3: f64,
-    pub so2: f64,
-    pub pm2_5: f64,
-    pub pm10: f64,
-    pub nh3: f64,
-}
\ No newline at end of file
+    pub co: Option<f64>,
+    pub no: Option<f64>,
+    pub no2: Option<f64>,
+    pub o3: Option<f64>,
+    pub so2: Option<f64>,
+    pub pm2_5: Option<f64>,
+    pub pm10: Option<f64>,
+    pub nh3: Option<f64>,
+}
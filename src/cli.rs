@@ -7,6 +7,7 @@ use std::{ffi::OsString, path::PathBuf};
 #[clap(
     about = "Display the current weather using the Openweather API.",
     setting = clap::AppSettings::NoAutoVersion,
+    disable_help_subcommand = true,
     mut_arg("help", |h| h.help_heading("INFO")),
     mut_arg("version", |h| h.help_heading("INFO")),
     version
@@ -19,7 +20,7 @@ pub struct ProgramOptions {
     ///   * be a relative path:  will be resolved relative to girouette's config directory
     ///   * start with '~/': will be resolved relative to the user's home directory
     ///   * be an absolute path.
-    /// 
+    ///
     /// This option overrides the corresponding value from the config.
     #[clap(short, long)]
     pub key: Option<OsString>,
@@ -35,9 +36,14 @@ pub struct ProgramOptions {
     /// Possible values are:
     ///   * Location names: "London, UK", "Dubai"
     ///   * Geographic coordinates (lat,lon): "35.68,139.69"
-    /// 
+    ///   * Postal codes (zip:code,country): "zip:75001,FR"
+    ///   * OpenWeather city IDs (id:ID): "id:2988507"
+    ///   * "auto", to find the location with the sources of the `geolocation` config section
+    ///
+    /// Can be given several times to show the weather at several locations.
+    ///
     /// This option overrides the corresponding value from the config.
-    pub location: Option<String>,
+    pub location: Vec<String>,
 
     #[clap(long, value_name = "FILE")]
     /// Use the specified configuration file instead of the default.
@@ -61,7 +67,7 @@ pub struct ProgramOptions {
     /// the one potentially querying it *both* need this option set.
     ///
     /// Recognized durations go from seconds ("seconds, second, sec, s") to years ("years, year, y").
-    /// 
+    ///
     /// This option overrides the corresponding value from the config.
     pub cache: Option<String>,
 
@@ -73,7 +79,7 @@ pub struct ProgramOptions {
     ///
     /// Possible values are of the form 'aa_AA' like 'en_US' or 'fr_FR'. Note that
     /// OpenWeather only supports a subset of all valid LANG values.
-    /// 
+    ///
     /// This option overrides the corresponding value from the config.
     pub language: Option<String>,
 
@@ -91,6 +97,68 @@ pub struct ProgramOptions {
     /// This option overrides the corresponding value from the config.
     pub units: Option<String>,
 
+    #[clap(long, possible_values(&["terminal", "json", "waybar", "i3bar", "polybar", "tmux", "bash", "zsh", "fish"]), value_name = "FORMAT")]
+    /// Format of the output.
+    ///
+    /// Possible formats are:
+    ///
+    /// - terminal: colored text for the terminal (the default),
+    ///
+    /// - json: a JSON document of the values displayed by each segment,
+    ///
+    /// - waybar: a JSON object for Waybar's custom modules, with the segments as Pango markup,
+    ///
+    /// - i3bar: the i3bar protocol, with one block per segment, updated every `interval`,
+    ///
+    /// - polybar: text with polybar/lemonbar formatting tags,
+    ///
+    /// - tmux: text with tmux style strings, for `status-left` or `status-right`,
+    ///
+    /// - bash, zsh, fish: colored text for the prompt of the given shell.
+    ///
+    /// This option overrides the corresponding value from the config.
+    pub output: Option<String>,
+
+    #[clap(short, long, value_name = "DURATION")]
+    /// Keep running and display the weather again every DURATION (e.g. "10m").
+    ///
+    /// Each update is printed on a new line, for bars reading the output line by line,
+    /// unless "--redraw" is given. Responses are still taken from the cache while they
+    /// are younger than the cache length.
+    ///
+    /// When the location is found with geoclue, the weather is also displayed again as
+    /// soon as the location changes.
+    ///
+    /// This option overrides the `interval` value from the config.
+    pub watch: Option<String>,
+
+    /// Run as a server sharing the weather between clients, over a Unix socket.
+    ///
+    /// The server keeps running, and answers "--client" invocations with the output of
+    /// a layout from the config. The weather is queried again once it is older than
+    /// `interval`.
+    #[clap(long, conflicts_with_all(&["watch", "client"]), help_heading = "FLAGS")]
+    pub server: bool,
+
+    #[clap(
+        long,
+        value_name = "LAYOUT",
+        min_values(0),
+        max_values(1),
+        default_missing_value("default")
+    )]
+    /// Print the output of a layout, as rendered by "girouette --server".
+    ///
+    /// Layouts are defined in the `layouts` section of the config of the server; the
+    /// "default" layout uses the main display options of the config.
+    pub client: Option<String>,
+
+    /// Redraw the same lines of the terminal on each update of "-w/--watch".
+    ///
+    /// Only supported with the terminal and shell outputs, when writing to a terminal.
+    #[clap(long, requires("watch"), help_heading = "FLAGS")]
+    pub redraw: bool,
+
     /// Run only offline with responses from the cache.
     ///
     /// The cache is used unconditionally, regardless of the cache length given in the
@@ -143,6 +211,40 @@ pub struct ProgramOptions {
     // Prints version information.
     //#[clap(short = 'V', long = "version", help_heading = "INFO")]
     //pub version: bool,
+    #[clap(subcommand)]
+    pub command: Option<ProgramCommand>,
+}
+
+#[derive(clap::Subcommand, Debug, Serialize, Deserialize)]
+pub enum ProgramCommand {
+    /// Search for places matching a name, with OpenWeather's geocoding API.
+    ///
+    /// Lists the matching places with their state, country and coordinates, to tell
+    /// apart places sharing the same name (e.g. "Springfield").
+    #[clap(disable_version_flag = true)]
+    Locate(LocateOptions),
+}
+
+#[derive(clap::Args, Debug, Serialize, Deserialize)]
+pub struct LocateOptions {
+    /// Name of the place to look for, optionally followed by a state and/or country
+    /// code (e.g. "Springfield, IL, US").
+    pub query: String,
+
+    /// Maximum number of places to list, from 1 to 5.
+    #[clap(
+        long,
+        default_value = "5",
+        value_name = "N",
+        value_parser = clap::value_parser!(u8).range(1..=5)
+    )]
+    pub limit: u8,
+
+    /// Save the coordinates of the Nth place listed as the `location` of the config.
+    ///
+    /// If there is no config file yet, it is created from the default configuration.
+    #[clap(long, value_name = "N")]
+    pub save: Option<usize>,
 }
 
 impl ProgramOptions {
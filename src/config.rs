@@ -1,13 +1,30 @@
-use crate::{segments::*, serde_utils::*, DisplayMode, Location, UnitMode};
+use crate::{
+    geoip, gpsd,
+    providers::{custom::CustomProviderConfig, openweather::OneCallVersion, ProviderKind},
+    segments::*,
+    serde_utils::*,
+    DisplayMode, Location, MultiLocation, OutputFormat, UnitMode,
+};
 use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf};
 use termcolor::{Color, ColorSpec};
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(default)]
 pub struct ProgramConfig {
+    #[serde(with = "one_or_many")]
+    pub provider: Vec<ProviderKind>,
+
+    pub custom_provider: Option<CustomProviderConfig>,
+
     pub key: Option<String>,
 
-    pub location: Option<Location>,
+    pub onecall_version: OneCallVersion,
+
+    #[serde(with = "one_or_many")]
+    pub location: Vec<Location>,
+
+    pub locations: HashMap<String, Location>,
 
     pub cache: Option<String>,
 
@@ -15,6 +32,14 @@ pub struct ProgramConfig {
 
     pub timeout: Option<String>,
 
+    pub interval: Option<String>,
+
+    pub socket: Option<PathBuf>,
+
+    pub layouts: HashMap<String, DisplayConfig>,
+
+    pub geolocation: GeolocationConfig,
+
     #[serde(flatten)]
     pub display_config: DisplayConfig,
 }
@@ -31,8 +56,17 @@ pub struct DisplayConfig {
 
     pub display_mode: DisplayMode,
 
+    pub output: OutputFormat,
+
+    pub multi_location: MultiLocation,
+
     #[serde(deserialize_with = "segment_vec::deserialize")]
     pub segments: Vec<Segment>,
+
+    #[serde(deserialize_with = "segment_vec::deserialize")]
+    pub tooltip: Vec<Segment>,
+
+    pub click_actions: HashMap<String, String>,
 }
 
 impl Default for DisplayConfig {
@@ -42,7 +76,98 @@ impl Default for DisplayConfig {
             separator: "  ".to_owned(),
             units: UnitMode::Metric,
             display_mode: DisplayMode::Unicode,
+            output: OutputFormat::Terminal,
+            multi_location: MultiLocation::Lines,
             segments: Vec::new(),
+            tooltip: vec![Segment::WeatherDescription(WeatherDescription::default())],
+            click_actions: HashMap::new(),
+        }
+    }
+}
+
+/// How to find the location when it is `auto` or not set.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct GeolocationConfig {
+    /// Where to look for the location, in order.
+    #[serde(with = "one_or_many")]
+    pub sources: Vec<LocationSource>,
+
+    /// Address of the gpsd daemon.
+    pub gpsd: String,
+
+    /// Service used to locate the IP address.
+    pub ip_url: String,
+
+    /// How long to reuse the location found from the IP address.
+    pub ip_cache: String,
+
+    /// Accuracy requested from geoclue.
+    pub geoclue_accuracy: AccuracyLevel,
+
+    /// Distance (in meters) the location must change by before geoclue reports it again.
+    pub geoclue_distance_threshold: u32,
+
+    /// Time that must pass before geoclue reports a new location.
+    pub geoclue_time_threshold: Option<String>,
+
+    /// How long to reuse the last location found by geoclue, or `none`.
+    pub geoclue_cache: String,
+}
+
+impl Default for GeolocationConfig {
+    fn default() -> Self {
+        GeolocationConfig {
+            sources: vec![LocationSource::Geoclue],
+            gpsd: gpsd::DEFAULT_ADDRESS.to_owned(),
+            ip_url: geoip::DEFAULT_URL.to_owned(),
+            ip_cache: "1 day".to_owned(),
+            geoclue_accuracy: AccuracyLevel::Exact,
+            geoclue_distance_threshold: 1000,
+            geoclue_time_threshold: None,
+            geoclue_cache: "5m".to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationSource {
+    Gpsd,
+    Geoclue,
+    Ip,
+}
+
+/// How precise the location found by geoclue should be.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccuracyLevel {
+    Country,
+    City,
+    Neighborhood,
+    Street,
+    Exact,
+}
+
+impl AccuracyLevel {
+    /// The value of geoclue's `GClueAccuracyLevel` enum.
+    pub fn geoclue_level(self) -> u32 {
+        match self {
+            AccuracyLevel::Country => 1,
+            AccuracyLevel::City => 4,
+            AccuracyLevel::Neighborhood => 5,
+            AccuracyLevel::Street => 6,
+            AccuracyLevel::Exact => 8,
+        }
+    }
+}
+
+impl std::fmt::Display for LocationSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LocationSource::Gpsd => write!(f, "gpsd"),
+            LocationSource::Geoclue => write!(f, "geoclue"),
+            LocationSource::Ip => write!(f, "IP geolocation"),
         }
     }
 }
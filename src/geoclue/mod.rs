@@ -4,17 +4,139 @@ pub mod manager;
 
 use crate::geoclue::client::*;
 use crate::geoclue::location::*;
-use crate::{geoclue::manager::*, Location};
-use anyhow::*;
+use crate::{
+    config::{AccuracyLevel, GeolocationConfig},
+    geoclue::manager::*,
+    Location, WeatherClient,
+};
+use anyhow::{anyhow, Context, Result};
 use dbus::message::SignalArgs;
-use dbus::nonblock;
+use dbus::nonblock::{self, SyncConnection};
+use dbus::Message;
 use dbus_tokio::connection;
-use futures_util::*;
+use futures_util::{stream::BoxStream, *};
 use log::*;
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tokio::time::timeout;
 
-pub async fn get_location(timeout_duration: Duration) -> Result<Location> {
+/// A location reported by geoclue.
+#[derive(Debug, Deserialize, Serialize)]
+struct Fix {
+    lat: f64,
+    lon: f64,
+    /// Radius of the area the location is in, in meters.
+    accuracy: f64,
+    /// When the location was found, in seconds since the UNIX epoch.
+    timestamp: u64,
+    /// The accuracy level requested when the location was found.
+    level: AccuracyLevel,
+}
+
+/// Finds the location with geoclue, or reuses the last location it found if it is recent
+/// enough (see `geoclue_cache`).
+pub async fn get_location(
+    conf: &GeolocationConfig,
+    timeout_duration: Duration,
+) -> Result<Location> {
+    let max_age = match conf.geoclue_cache.as_str() {
+        "none" => None,
+        c => Some(
+            humantime::parse_duration(c)
+                .context("failed to parse geolocation.geoclue_cache: not a valid duration")?,
+        ),
+    };
+
+    if let Some(max_age) = max_age {
+        match read_last_fix(max_age, conf.geoclue_accuracy) {
+            Ok(Some(fix)) => {
+                info!(
+                    "using last location from geoclue (accurate to {:.0}m)",
+                    fix.accuracy
+                );
+                return Ok(Location::LatLon(fix.lat, fix.lon));
+            }
+            Ok(None) => {}
+            Err(e) => warn!("error while reading the last location from geoclue: {}", e),
+        }
+    }
+
+    let fix = locate(conf, timeout_duration).await?;
+    debug!("geoclue location is accurate to {:.0}m", fix.accuracy);
+
+    if max_age.is_some() {
+        save_fix(&fix);
+    }
+
+    Ok(Location::LatLon(fix.lat, fix.lon))
+}
+
+fn last_fix_path() -> Result<PathBuf> {
+    let dirs = WeatherClient::directories()
+        .ok_or_else(|| anyhow!("Count not locate project directory!"))?;
+    let dir = dirs.cache_dir().join("results");
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir.join("geoclue.json"))
+}
+
+fn save_fix(fix: &Fix) {
+    let res = last_fix_path().and_then(|p| Ok(std::fs::write(p, serde_json::to_vec(fix)?)?));
+    if let Err(e) = res {
+        warn!("error while writing the last location from geoclue: {}", e);
+    }
+}
+
+fn read_last_fix(max_age: Duration, level: AccuracyLevel) -> Result<Option<Fix>> {
+    let path = last_fix_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let fix: Fix = serde_json::from_slice(&std::fs::read(&path)?)?;
+    let age = SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .saturating_sub(Duration::from_secs(fix.timestamp));
+    if age > max_age {
+        info!(
+            "ignoring last location from geoclue, found {}s ago",
+            age.as_secs()
+        );
+        return Ok(None);
+    }
+    if fix.level < level {
+        info!("ignoring last location from geoclue, found with a lower accuracy level");
+        return Ok(None);
+    }
+
+    Ok(Some(fix))
+}
+
+async fn locate(conf: &GeolocationConfig, timeout_duration: Duration) -> Result<Fix> {
+    let (conn, incoming, mut updates) = start_client(conf).await?;
+
+    let (_, update) = timeout(timeout_duration, updates.next())
+        .await
+        .map_err(|_| anyhow!("geoclue timed-out trying to find your location"))?
+        .ok_or_else(|| anyhow!("no location"))?;
+
+    conn.remove_match(incoming.token())
+        .await
+        .context("D-bus error")?;
+
+    read_fix(&conn, update.new, conf.geoclue_accuracy).await
+}
+
+type Updates = BoxStream<'static, (Message, OrgFreedesktopGeoClue2ClientLocationUpdated)>;
+
+/// Starts a geoclue client, returning the signals it sends when the location changes.
+async fn start_client(
+    conf: &GeolocationConfig,
+) -> Result<(Arc<SyncConnection>, nonblock::MsgMatch, Updates)> {
     let (resource, conn) = connection::new_system_sync()?;
 
     tokio::spawn(async {
@@ -39,7 +161,7 @@ pub async fn get_location(timeout_duration: Duration) -> Result<Location> {
         conn.clone(),
     );
 
-    let (incoming, mut stream) = conn
+    let (incoming, stream) = conn
         .add_match(OrgFreedesktopGeoClue2ClientLocationUpdated::match_rule(
             None, None,
         ))
@@ -53,20 +175,33 @@ pub async fn get_location(timeout_duration: Duration) -> Result<Location> {
         .await
         .context("D-bus error")?;
 
-    client.start().await.context("D-bus error")?;
-
-    let res: (_, OrgFreedesktopGeoClue2ClientLocationUpdated) =
-        timeout(timeout_duration, stream.next())
-            .await
-            .map_err(|_| anyhow!("geoclue timed-out trying to find your location"))?
-            .ok_or_else(|| anyhow!("no location"))?;
-
-    conn.remove_match(incoming.token())
+    client
+        .set_requested_accuracy_level(conf.geoclue_accuracy.geoclue_level())
+        .await
+        .context("D-bus error")?;
+    client
+        .set_distance_threshold(conf.geoclue_distance_threshold)
         .await
         .context("D-bus error")?;
+    if let Some(t) = &conf.geoclue_time_threshold {
+        let t = humantime::parse_duration(t)
+            .context("failed to parse geolocation.geoclue_time_threshold: not a valid duration")?;
+        client
+            .set_time_threshold(t.as_secs() as u32)
+            .await
+            .context("D-bus error")?;
+    }
+
+    client.start().await.context("D-bus error")?;
 
-    let location_path = res.1.new;
+    Ok((conn, incoming, stream.boxed()))
+}
 
+async fn read_fix(
+    conn: &Arc<SyncConnection>,
+    location_path: dbus::Path<'static>,
+    level: AccuracyLevel,
+) -> Result<Fix> {
     trace!("location path: {}", location_path);
 
     let location = nonblock::Proxy::new(
@@ -78,6 +213,106 @@ pub async fn get_location(timeout_duration: Duration) -> Result<Location> {
 
     let lat = location.latitude().await.context("D-bus error")?;
     let lon = location.longitude().await.context("D-bus error")?;
+    let accuracy = location.accuracy().await.context("D-bus error")?;
+    let (timestamp, _) = location.timestamp().await.context("D-bus error")?;
+
+    Ok(Fix {
+        lat,
+        lon,
+        accuracy,
+        timestamp,
+        level,
+    })
+}
+
+/// A geoclue client kept running, to follow the location as it changes.
+pub struct Tracker {
+    conn: Arc<SyncConnection>,
+    updates: Updates,
+    level: AccuracyLevel,
+    threshold: f64,
+    save: bool,
+    last: Option<(f64, f64)>,
+}
+
+impl Tracker {
+    /// Starts following the location, from the `current` one.
+    pub async fn start(conf: &GeolocationConfig, current: &Location) -> Result<Self> {
+        let (conn, _, updates) = start_client(conf).await?;
+
+        Ok(Tracker {
+            conn,
+            updates,
+            level: conf.geoclue_accuracy,
+            threshold: conf.geoclue_distance_threshold as f64,
+            save: conf.geoclue_cache != "none",
+            last: match current {
+                Location::LatLon(lat, lon) => Some((*lat, *lon)),
+                _ => None,
+            },
+        })
+    }
+
+    /// Waits until geoclue reports a location further than the distance threshold from
+    /// the last one.
+    pub async fn next_location(&mut self) -> Result<Location> {
+        loop {
+            let (_, update) = self
+                .updates
+                .next()
+                .await
+                .ok_or_else(|| anyhow!("geoclue stopped reporting the location"))?;
+            let fix = read_fix(&self.conn, update.new, self.level).await?;
+
+            if let Some(last) = self.last {
+                let moved = distance(last, (fix.lat, fix.lon));
+                if moved < self.threshold {
+                    debug!(
+                        "ignoring new location from geoclue, only {:.0}m away",
+                        moved
+                    );
+                    continue;
+                }
+            }
+            self.last = Some((fix.lat, fix.lon));
+
+            if self.save {
+                save_fix(&fix);
+            }
+
+            return Ok(Location::LatLon(fix.lat, fix.lon));
+        }
+    }
+}
+
+/// The distance between two points, in meters.
+fn distance((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
+    const EARTH_RADIUS: f64 = 6_371_000.0;
+
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (lon2 - lon1).to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paris_london() {
+        let paris = (48.8566, 2.3522);
+        let london = (51.5074, -0.1278);
+        // about 343.5 km as the crow flies
+        let d = distance(paris, london);
+        assert!((d - 343_500.0).abs() < 1_000.0, "{}", d);
+        assert_eq!(d, distance(london, paris));
+    }
 
-    Ok(Location::LatLon(lat, lon))
+    #[test]
+    fn same_point() {
+        assert_eq!(distance((48.8566, 2.3522), (48.8566, 2.3522)), 0.0);
+    }
 }
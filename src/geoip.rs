@@ -0,0 +1,146 @@
+//! Geolocation from the public IP address, with a web service such as ipinfo.io.
+//!
+//! This is much less precise than geoclue (usually the city of the internet provider),
+//! but works anywhere there is a network: on servers, in containers, or in static builds.
+
+use crate::{Location, WeatherClient};
+use anyhow::{anyhow, bail, Context, Result};
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The service used when none is configured.
+pub const DEFAULT_URL: &str = "https://ipinfo.io/json";
+
+#[derive(Deserialize, Serialize)]
+struct CacheEntry {
+    url: String,
+    lat: f64,
+    lon: f64,
+}
+
+/// Finds the location of the public IP address with the service at `url`.
+///
+/// The location is cached, and reused for `cache_length`; when `offline`, only the
+/// cached location is used, however old it is.
+pub async fn get_location(
+    client: &WeatherClient,
+    url: &str,
+    cache_length: Duration,
+    offline: bool,
+) -> Result<Location> {
+    let path = cache_path()?;
+    match read_cache(&path, url, cache_length, offline) {
+        Ok(Some(loc)) => return Ok(loc),
+        Ok(None) => {}
+        Err(e) if offline => return Err(e),
+        Err(e) => warn!("error while reading cached IP location: {}", e),
+    }
+
+    debug!("querying IP location from '{}'", url);
+    let bytes = client.fetch(client.http().get(url), url).await?;
+    let value: Value =
+        serde_json::from_slice(&bytes).with_context(|| format!("invalid answer from '{}'", url))?;
+    let (lat, lon) =
+        parse(&value).ok_or_else(|| anyhow!("no coordinates in the answer from '{}'", url))?;
+
+    let entry = CacheEntry {
+        url: url.to_owned(),
+        lat,
+        lon,
+    };
+    if let Err(e) = std::fs::write(&path, serde_json::to_vec(&entry)?) {
+        warn!("error while writing cached IP location: {}", e);
+    }
+
+    Ok(Location::LatLon(lat, lon))
+}
+
+fn cache_path() -> Result<PathBuf> {
+    let dirs = WeatherClient::directories()
+        .ok_or_else(|| anyhow!("Count not locate project directory!"))?;
+    let dir = dirs.cache_dir().join("results");
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir.join("geoip.json"))
+}
+
+fn read_cache(
+    path: &Path,
+    url: &str,
+    cache_length: Duration,
+    offline: bool,
+) -> Result<Option<Location>> {
+    if !path.exists() {
+        if offline {
+            bail!("failed to find a cached IP location, but running offline");
+        }
+        return Ok(None);
+    }
+
+    let entry: CacheEntry = serde_json::from_slice(&std::fs::read(path)?)?;
+    if entry.url != url && !offline {
+        debug!("ignoring IP location cached from '{}'", entry.url);
+        return Ok(None);
+    }
+    if !offline && path.metadata()?.modified()?.elapsed()? > cache_length {
+        info!("ignoring expired cached IP location");
+        return Ok(None);
+    }
+
+    info!("using cached IP location");
+    Ok(Some(Location::LatLon(entry.lat, entry.lon)))
+}
+
+/// Finds the coordinates in the answers of the most common services: `lat`/`lon`
+/// (ip-api.com), `latitude`/`longitude` (ipapi.co, ipwho.is) or `loc: "lat,lon"`
+/// (ipinfo.io).
+fn parse(value: &Value) -> Option<(f64, f64)> {
+    let number = |v: &Value| v.as_f64().or_else(|| v.as_str()?.trim().parse().ok());
+
+    if let (Some(lat), Some(lon)) = (value.get("lat"), value.get("lon")) {
+        return Some((number(lat)?, number(lon)?));
+    }
+    if let (Some(lat), Some(lon)) = (value.get("latitude"), value.get("longitude")) {
+        return Some((number(lat)?, number(lon)?));
+    }
+
+    let (lat, lon) = value.get("loc")?.as_str()?.split_once(',')?;
+    Some((lat.trim().parse().ok()?, lon.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn lat_lon() {
+        let value = json!({ "status": "success", "lat": 48.8566, "lon": 2.3522 });
+        assert_eq!(parse(&value), Some((48.8566, 2.3522)));
+    }
+
+    #[test]
+    fn latitude_longitude() {
+        let value = json!({ "ip": "1.2.3.4", "latitude": "48.8566", "longitude": 2.3522 });
+        assert_eq!(parse(&value), Some((48.8566, 2.3522)));
+    }
+
+    #[test]
+    fn loc() {
+        let value = json!({ "ip": "1.2.3.4", "city": "Paris", "loc": "48.8566, 2.3522" });
+        assert_eq!(parse(&value), Some((48.8566, 2.3522)));
+    }
+
+    #[test]
+    fn malformed_loc() {
+        assert_eq!(parse(&json!({ "loc": "48.8566" })), None);
+        assert_eq!(parse(&json!({ "loc": "48.8566,east" })), None);
+        assert_eq!(parse(&json!({ "loc": [48.8566, 2.3522] })), None);
+        assert_eq!(parse(&json!({ "ip": "1.2.3.4" })), None);
+    }
+}
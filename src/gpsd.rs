@@ -0,0 +1,161 @@
+//! Location from a GPS receiver, through a gpsd daemon.
+//!
+//! gpsd speaks JSON over TCP: once a client asks to watch the devices, it sends a report
+//! per line, and the position is given by the `TPV` (time-position-velocity) reports.
+
+use crate::Location;
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    time::timeout,
+};
+
+/// The address gpsd listens on by default.
+pub const DEFAULT_ADDRESS: &str = "localhost:2947";
+
+const WATCH: &[u8] = b"?WATCH={\"enable\":true,\"json\":true}\n";
+
+#[derive(Deserialize, Debug)]
+struct Report {
+    class: String,
+    /// 0 or 1 without a fix, 2 for a 2D fix and 3 for a 3D fix.
+    #[serde(default)]
+    mode: u8,
+    lat: Option<f64>,
+    lon: Option<f64>,
+}
+
+/// Waits for the gpsd daemon at `address` to report a position, for at most `timeout_duration`.
+pub async fn get_location(address: &str, timeout_duration: Duration) -> Result<Location> {
+    timeout(timeout_duration, wait_for_fix(address))
+        .await
+        .map_err(|_| anyhow!("gpsd didn't report a position in time"))?
+}
+
+async fn wait_for_fix(address: &str) -> Result<Location> {
+    let mut stream = TcpStream::connect(address)
+        .await
+        .with_context(|| format!("could not connect to gpsd at '{}'", address))?;
+    let (read, mut write) = stream.split();
+
+    write.write_all(WATCH).await?;
+
+    let mut lines = BufReader::new(read).lines();
+    while let Some(line) = lines.next_line().await? {
+        trace!("gpsd: {}", line);
+        if let Some(loc) = parse_fix(&line) {
+            return Ok(loc);
+        }
+    }
+
+    Err(anyhow!(
+        "gpsd closed the connection without reporting a position"
+    ))
+}
+
+/// The position in a report from gpsd, if it is a `TPV` report with a fix.
+fn parse_fix(line: &str) -> Option<Location> {
+    let report: Report = match serde_json::from_str(line) {
+        Ok(r) => r,
+        Err(e) => {
+            debug!("ignoring invalid report from gpsd: {}", e);
+            return None;
+        }
+    };
+
+    if report.class != "TPV" {
+        return None;
+    }
+    match (report.mode, report.lat, report.lon) {
+        (2.., Some(lat), Some(lon)) => Some(Location::LatLon(lat, lon)),
+        _ => {
+            debug!("no fix from gpsd yet");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tpv_with_fix() {
+        let line = r#"{"class":"TPV","device":"/dev/ttyUSB0","mode":3,"time":"2024-05-01T12:00:00.000Z","lat":48.8566,"lon":2.3522,"alt":35.0}"#;
+        assert!(matches!(
+            parse_fix(line),
+            Some(Location::LatLon(lat, lon)) if lat == 48.8566 && lon == 2.3522
+        ));
+
+        let line = r#"{"class":"TPV","mode":2,"lat":-33.87,"lon":151.21}"#;
+        assert!(matches!(parse_fix(line), Some(Location::LatLon(..))));
+    }
+
+    #[test]
+    fn tpv_without_fix() {
+        assert!(parse_fix(r#"{"class":"TPV","mode":1}"#).is_none());
+        assert!(parse_fix(r#"{"class":"TPV","mode":0,"lat":48.8,"lon":2.3}"#).is_none());
+        assert!(parse_fix(r#"{"class":"TPV"}"#).is_none());
+        assert!(parse_fix(r#"{"class":"TPV","mode":3,"lat":48.8}"#).is_none());
+    }
+
+    /// Plays gpsd on a local port: waits for the client to watch the devices, then sends
+    /// `reports` and closes the connection.
+    async fn fake_gpsd(reports: &'static [&'static str]) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.split();
+            let mut watch = String::new();
+            BufReader::new(read).read_line(&mut watch).await.unwrap();
+            assert_eq!(watch.as_bytes(), WATCH);
+
+            for report in reports {
+                write.write_all(report.as_bytes()).await.unwrap();
+                write.write_all(b"\n").await.unwrap();
+            }
+        });
+
+        address
+    }
+
+    #[tokio::test]
+    async fn waits_for_a_fix() {
+        let address = fake_gpsd(&[
+            r#"{"class":"VERSION","release":"3.22","proto_major":3,"proto_minor":14}"#,
+            r#"{"class":"DEVICES","devices":[{"class":"DEVICE","path":"/dev/ttyUSB0"}]}"#,
+            r#"{"class":"TPV","device":"/dev/ttyUSB0","mode":1}"#,
+            r#"{"class":"TPV","device":"/dev/ttyUSB0","mode":3,"lat":45.76,"lon":4.84}"#,
+        ])
+        .await;
+
+        let loc = get_location(&address, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(matches!(loc, Location::LatLon(lat, lon) if lat == 45.76 && lon == 4.84));
+    }
+
+    #[tokio::test]
+    async fn closed_without_fix() {
+        let address = fake_gpsd(&[r#"{"class":"TPV","mode":1}"#]).await;
+
+        let e = get_location(&address, Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("without reporting a position"));
+    }
+
+    #[test]
+    fn other_reports() {
+        assert!(parse_fix(r#"{"class":"VERSION","release":"3.22","proto_major":3}"#).is_none());
+        assert!(parse_fix(r#"{"class":"SKY","mode":3,"lat":48.8,"lon":2.3}"#).is_none());
+        assert!(parse_fix("not json").is_none());
+        assert!(parse_fix("").is_none());
+    }
+}
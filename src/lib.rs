@@ -3,31 +3,47 @@ pub mod cli;
 pub mod config;
 #[cfg(feature = "geoclue")]
 pub mod geoclue;
+pub mod geoip;
+pub mod gpsd;
+pub mod markup;
+pub mod providers;
 pub mod segments;
 mod serde_utils;
+#[cfg(unix)]
+pub mod server;
 
-use std::{borrow::Cow, fmt::Display, path::Path, time::Duration};
+use std::{collections::HashMap, fmt::Display, io::Write, path::Path, time::Duration};
 
 use crate::config::DisplayConfig;
 use anyhow::{bail, Context, Result};
-use api::{current::ApiResponse as CResponse, one_call::ApiResponse as OResponse, pollution::ApiResponse as PResponse, Response};
+use api::Response;
 use directories_next::ProjectDirs;
 use log::*;
+use markup::Shell;
+use providers::WeatherProvider;
 use reqwest::StatusCode;
 use segments::Renderer;
 use serde::{Deserialize, Serialize};
-use termcolor::StandardStream;
-use tokio::time::timeout;
+use termcolor::{Buffer, ColorSpec, WriteColor};
+use tokio::{sync::watch, time::timeout};
 
-const CURRENT_API_URL: &str = "https://api.openweathermap.org/data/2.5/weather";
-const ONECALL_API_URL: &str = "https://api.openweathermap.org/data/2.5/onecall";
-const POLLUTION_API_URL: &str = "http://api.openweathermap.org/data/2.5/air_pollution";
+const USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    " ",
+    env!("CARGO_PKG_HOMEPAGE")
+);
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(from = "String", into = "String")]
 pub enum Location {
     LatLon(f64, f64),
     Place(String),
+    /// A postal code, and the code of its country.
+    Zip(String, String),
+    /// An OpenWeather city ID.
+    CityId(u32),
 }
 
 impl From<String> for Location {
@@ -47,6 +63,8 @@ impl std::fmt::Display for Location {
         match self {
             Location::LatLon(lat, lon) => write!(f, "{}, {}", lat, lon),
             Location::Place(place) => write!(f, "{}", place),
+            Location::Zip(code, country) => write!(f, "zip:{},{}", code, country),
+            Location::CityId(id) => write!(f, "id:{}", id),
         }
     }
 }
@@ -58,8 +76,34 @@ pub enum WindType {
 }
 
 impl Location {
+    /// The location `self` is an alias for, or `self` if it isn't one.
+    pub fn resolve<'a>(&'a self, aliases: &'a HashMap<String, Location>) -> &'a Location {
+        match self {
+            Location::Place(name) => match aliases.get(name) {
+                Some(loc) => {
+                    debug!("'{}' is an alias for '{}'", name, loc);
+                    loc
+                }
+                None => self,
+            },
+            loc => loc,
+        }
+    }
+
     pub fn new(s: &str) -> Location {
-        let sp: Vec<_> = s.split(',').collect();
+        if let Some(zip) = s.strip_prefix("zip:") {
+            // OpenWeather assumes the USA without a country
+            let (code, country) = zip.split_once(',').unwrap_or((zip, "US"));
+            return Location::Zip(code.trim().to_owned(), country.trim().to_owned());
+        }
+        if let Some(id) = s.strip_prefix("id:") {
+            match id.trim().parse() {
+                Ok(id) => return Location::CityId(id),
+                Err(_) => debug!("could not parse '{}' as 'id:ID', assuming it is a place", s),
+            }
+        }
+
+        let sp: Vec<_> = s.split(',').map(str::trim).collect();
         if sp.len() == 2 {
             if let (Ok(lat), Ok(lon)) = (sp[0].parse(), sp[1].parse()) {
                 return Location::LatLon(lat, lon);
@@ -77,79 +121,347 @@ impl Location {
 
 pub struct Girouette {
     config: DisplayConfig,
+    providers: Vec<Box<dyn WeatherProvider>>,
+    aliases: HashMap<String, Location>,
     cache_length: Option<Duration>,
     timeout: Duration,
-    key: String,
     language: Option<String>,
 }
 
 impl Girouette {
     pub fn new(
         config: DisplayConfig,
+        providers: Vec<Box<dyn WeatherProvider>>,
+        aliases: HashMap<String, Location>,
         cache_length: Option<Duration>,
         timeout: Duration,
-        key: String,
         language: Option<String>,
     ) -> Self {
         Self {
             config,
+            providers,
+            aliases,
             cache_length,
             timeout,
-            key,
             language,
         }
     }
 
-    pub async fn display(
+    /// Displays the weather again every `interval`, until the program is killed.
+    ///
+    /// Each update is written on a new line, or over the previous one if `redraw` is true.
+    /// Errors are logged instead of stopping the updates.
+    ///
+    /// The locations are read from `locs` before each update, and the weather is displayed
+    /// again right away when they change (e.g. when following the location from geoclue).
+    ///
+    /// With the i3bar output, this is the whole i3bar protocol, and errors are shown in
+    /// an urgent block.
+    pub async fn watch<W: WriteColor>(
+        &self,
+        mut locs: watch::Receiver<Vec<Location>>,
+        offline: bool,
+        out: &mut W,
+        interval: Duration,
+        redraw: bool,
+    ) -> Result<()> {
+        let i3bar = self.config.output == OutputFormat::I3bar;
+        if i3bar {
+            writeln!(out, "{{\"version\":1}}")?;
+            writeln!(out, "[")?;
+        }
+
+        // newlines written by the previous update
+        let mut newlines = 0;
+        loop {
+            if redraw {
+                // back to the start of the line, and clear it, then the lines above it
+                write!(out, "\r\x1b[2K")?;
+                for _ in 0..newlines {
+                    write!(out, "\x1b[1A\x1b[2K")?;
+                }
+            }
+
+            let current = locs.borrow().clone();
+            let mut counter = NewlineCounter::new(&mut *out);
+            match self.display_all(&current, offline, &mut counter).await {
+                Ok(()) => {
+                    if !redraw && !self.config.output.ends_with_newline() {
+                        writeln!(counter)?;
+                    }
+                }
+                Err(e) if i3bar => {
+                    warn!("{:#}", e);
+                    let block = serde_json::json!([{
+                        "name": "error",
+                        "full_text": format!("girouette: {}", e),
+                        "urgent": true,
+                    }]);
+                    serde_json::to_writer(&mut counter, &block)?;
+                    writeln!(counter, ",")?;
+                }
+                Err(e) => warn!("{:#}", e),
+            }
+            newlines = counter.newlines;
+            out.flush()?;
+
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                Ok(()) = locs.changed() => debug!("locations changed, displaying the weather again"),
+            }
+        }
+    }
+
+    /// Queries the weather and displays it in the configured output format, into a string.
+    ///
+    /// With the terminal output, styles are written as ANSI escape codes if `colored` is
+    /// true, and ignored otherwise.
+    pub async fn display_to_string(
         &self,
         loc: &Location,
         offline: bool,
-        out: &mut StandardStream,
+        colored: bool,
+    ) -> Result<String> {
+        let mut buffer = if colored {
+            Buffer::ansi()
+        } else {
+            Buffer::no_color()
+        };
+        self.display(loc, offline, &mut buffer).await?;
+
+        String::from_utf8(buffer.into_inner()).context("rendered text isn't valid UTF-8")
+    }
+
+    /// Queries the weather at each location and displays it in the configured output
+    /// format, on one line per location or a combined line (see [`MultiLocation`]).
+    pub async fn display_all<W: WriteColor>(
+        &self,
+        locs: &[Location],
+        offline: bool,
+        out: &mut W,
     ) -> Result<()> {
-        let mut renderer = Renderer::from(&self.config);
+        let loc = match locs {
+            [] => bail!("no location to query"),
+            [loc] => return self.display(loc, offline, out).await,
+            [loc, ..] => loc,
+        };
+        if matches!(
+            self.config.output,
+            OutputFormat::Json | OutputFormat::Waybar | OutputFormat::I3bar
+        ) {
+            warn!(
+                "the json, waybar and i3bar outputs only support one location, ignoring all but {}",
+                loc
+            );
+            return self.display(loc, offline, out).await;
+        }
+
+        // query every location first, so that nothing is written if one of them fails
+        let mut texts = Vec::with_capacity(locs.len());
+        for loc in locs {
+            let mut buffer = if out.supports_color() {
+                Buffer::ansi()
+            } else {
+                Buffer::no_color()
+            };
+            self.display(loc, offline, &mut buffer).await?;
+            let text = String::from_utf8(buffer.into_inner())
+                .context("rendered text isn't valid UTF-8")?;
+            texts.push(text.trim_end_matches('\n').to_owned());
+        }
+
+        let combined = self.config.multi_location == MultiLocation::Combined;
+        let renderer = Renderer::from(&self.config);
+        for (i, (loc, text)) in locs.iter().zip(&texts).enumerate() {
+            if combined {
+                if i > 0 {
+                    renderer.render_text(out, &self.config.separator)?;
+                }
+                renderer.render_text(out, &format!("{}: ", loc))?;
+                write!(out, "{}", text)?;
+            } else {
+                if i > 0 {
+                    writeln!(out)?;
+                }
+                write!(out, "{}", text)?;
+            }
+        }
+        if self.config.output.ends_with_newline() {
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
 
+    /// Queries the weather and displays it in the configured output format.
+    pub async fn display<W: WriteColor>(
+        &self,
+        loc: &Location,
+        offline: bool,
+        out: &mut W,
+    ) -> Result<()> {
+        let renderer = Renderer::from(&self.config);
         let kinds = renderer.display_kinds()?;
+        let units = self.config.units;
+        let mut response = self.query(loc, offline, &kinds, units).await?;
+        let tooltip_kinds = renderer.tooltip_kinds()?;
+        self.query_optional(&mut response, loc, offline, &tooltip_kinds, units)
+            .await;
+
+        self.render(&self.config, &response, out)
+    }
+
+    /// Queries the given kinds of weather data at a location, or at the location an alias
+    /// stands for.
+    pub async fn query(
+        &self,
+        loc: &Location,
+        offline: bool,
+        kinds: &[QueryKind],
+        units: UnitMode,
+    ) -> Result<Response> {
+        let loc = loc.resolve(&self.aliases);
+        let client = WeatherClient::new(self.cache_length, self.timeout);
 
         let mut response = Response::empty();
-        if kinds.contains(&QueryKind::Current) || (matches!(loc, Location::Place(_))) {
-            let res = WeatherClient::new(self.cache_length, self.timeout)
-                .query(
+        // forecasts and pollution need coordinates, given with the current weather
+        let needs_coords = !matches!(loc, Location::LatLon(..));
+        if kinds.contains(&QueryKind::Current) || needs_coords {
+            let res = client
+                .query_first(
+                    &self.providers,
                     QueryKind::Current,
                     loc,
-                    self.key.clone(),
                     self.language.as_deref(),
-                    self.config.units,
+                    units,
                     offline,
                 )
                 .await?;
             response.merge(res);
         }
-        let new_loc = if let Location::Place(_) = loc {
+        let new_loc = if needs_coords {
             let resp = response.as_current()?;
             Location::LatLon(resp.coord.lat, resp.coord.lon)
         } else {
             loc.clone()
         };
 
-        for kind in kinds {
+        for &kind in kinds {
             if kind == QueryKind::Current {
                 continue;
             }
+            if kind == QueryKind::LocationName {
+                // the current weather already has it
+                if response.as_current().is_err() {
+                    response.merge(
+                        self.query_location_name(&client, &new_loc, offline, units)
+                            .await?,
+                    );
+                }
+                continue;
+            }
 
-            let res = WeatherClient::new(self.cache_length, self.timeout)
-                .query(
+            let res = client
+                .query_first(
+                    &self.providers,
                     kind,
                     &new_loc,
-                    self.key.clone(),
                     self.language.as_deref(),
-                    self.config.units,
+                    units,
                     offline,
                 )
                 .await?;
             response.merge(res);
         }
 
-        renderer.render(out, &response, self.language.as_deref())?;
+        Ok(response)
+    }
+
+    /// Queries more kinds of weather data into `response`, only logging the errors: this is
+    /// for data that can be left out, like the one shown in tooltips.
+    pub async fn query_optional(
+        &self,
+        response: &mut Response,
+        loc: &Location,
+        offline: bool,
+        kinds: &[QueryKind],
+        units: UnitMode,
+    ) {
+        if kinds.is_empty() {
+            return;
+        }
+
+        // reuse the coordinates found with the current weather
+        let loc = match response.as_current() {
+            Ok(current) => Location::LatLon(current.coord.lat, current.coord.lon),
+            Err(_) => loc.clone(),
+        };
+        match self.query(&loc, offline, kinds, units).await {
+            Ok(res) => response.merge(res),
+            Err(e) => warn!("leaving out {:?} data: {:#}", kinds, e),
+        }
+    }
+
+    /// Finds the name of the place at the given coordinates, falling back on the name given
+    /// with the current weather if no provider can do reverse geocoding.
+    async fn query_location_name(
+        &self,
+        client: &WeatherClient,
+        loc: &Location,
+        offline: bool,
+        units: UnitMode,
+    ) -> Result<Response> {
+        let language = self.language.as_deref();
+        let res = client
+            .query_first(
+                &self.providers,
+                QueryKind::LocationName,
+                loc,
+                language,
+                units,
+                offline,
+            )
+            .await;
+
+        match res {
+            Ok(res) => Ok(res),
+            Err(e) => {
+                info!("no location name for {}: {:#}", loc, e);
+                client
+                    .query_first(
+                        &self.providers,
+                        QueryKind::Current,
+                        loc,
+                        language,
+                        units,
+                        offline,
+                    )
+                    .await
+            }
+        }
+    }
+
+    /// Renders weather data with a display configuration, in its output format.
+    pub fn render<W: WriteColor>(
+        &self,
+        config: &DisplayConfig,
+        response: &Response,
+        out: &mut W,
+    ) -> Result<()> {
+        let mut renderer = Renderer::from(config);
+        let language = self.language.as_deref();
+
+        match config.output {
+            OutputFormat::Terminal => renderer.render(out, response, language)?,
+            OutputFormat::Json => renderer.render_json(out, response, language)?,
+            OutputFormat::I3bar => renderer.render_i3bar(out, response, language)?,
+            OutputFormat::Polybar => renderer.render_polybar(out, response, language)?,
+            OutputFormat::Tmux => renderer.render_tmux(out, response, language)?,
+            OutputFormat::Bash => renderer.render_prompt(out, response, language, Shell::Bash)?,
+            OutputFormat::Zsh => renderer.render_prompt(out, response, language, Shell::Zsh)?,
+            OutputFormat::Fish => renderer.render_prompt(out, response, language, Shell::Fish)?,
+            OutputFormat::Waybar => renderer.render_waybar(out, response, language)?,
+        }
 
         Ok(())
     }
@@ -163,8 +475,14 @@ pub struct WeatherClient {
 
 impl WeatherClient {
     pub fn new(cache_length: Option<Duration>, timeout: Duration) -> Self {
+        // some providers (e.g. MET Norway) refuse requests without an identifying user-agent
+        let client = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+
         WeatherClient {
-            client: reqwest::Client::new(),
+            client,
             cache_length,
             timeout,
         }
@@ -185,8 +503,158 @@ impl WeatherClient {
         ProjectDirs::from("rs", "", "Girouette")
     }
 
+    /// The HTTP client used to build requests to weather providers.
+    pub fn http(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Sends a request and reads the whole response body, giving up after the configured timeout.
+    pub async fn fetch(&self, request: reqwest::RequestBuilder, host: &str) -> Result<Vec<u8>> {
+        let response = self.send(request, host).await?;
+
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            return Err(StatusError::new(host, status).into());
+        }
+
+        self.read(response, host).await
+    }
+
+    /// Sends a request, honoring the `Expires` and `Last-Modified` headers of a previous response.
+    ///
+    /// If a previous response with the same `key` hasn't expired yet, it is returned without
+    /// querying the network. Otherwise the request is sent with an `If-Modified-Since` header,
+    /// and the previous response is reused if the server answers that it is still valid.
+    /// Responses are only remembered if caching is enabled.
+    pub async fn fetch_conditional(
+        &self,
+        mut request: reqwest::RequestBuilder,
+        host: &str,
+        key: &str,
+    ) -> Result<Vec<u8>> {
+        let path = match self.cache_length {
+            Some(_) => Some(self.find_http_cache_for(key)?),
+            None => None,
+        };
+
+        let previous: Option<HttpCacheEntry> =
+            path.as_deref()
+                .filter(|p| p.exists())
+                .and_then(|p| match std::fs::read(p) {
+                    Ok(b) => serde_json::from_slice(&b).ok(),
+                    Err(e) => {
+                        warn!("error while reading cached response: {}", e);
+                        None
+                    }
+                });
+
+        if let Some(previous) = &previous {
+            if previous.expires > Some(chrono::Utc::now().timestamp()) {
+                info!("using unexpired cached response from {}", host);
+                return Ok(previous.body.clone().into_bytes());
+            }
+            if let Some(last_modified) = &previous.last_modified {
+                request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = self.send(request, host).await?;
+        let status = response.status();
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_owned())
+        };
+        let expires = header(reqwest::header::EXPIRES)
+            .and_then(|e| chrono::DateTime::parse_from_rfc2822(&e).ok())
+            .map(|e| e.timestamp());
+        let last_modified = header(reqwest::header::LAST_MODIFIED);
+
+        let entry = match previous {
+            Some(mut previous) if status == StatusCode::NOT_MODIFIED => {
+                info!("cached response from {} is still valid", host);
+                previous.expires = expires;
+                previous
+            }
+            _ if status.is_success() => {
+                let bytes = self.read(response, host).await?;
+                HttpCacheEntry {
+                    expires,
+                    last_modified,
+                    body: String::from_utf8(bytes)?,
+                }
+            }
+            _ => return Err(StatusError::new(host, status).into()),
+        };
+
+        if let Some(path) = path {
+            debug!("writing cache for {}", key);
+            let write = || -> Result<()> {
+                std::fs::write(&path, serde_json::to_vec(&entry)?)?;
+                Ok(())
+            };
+            if let Err(e) = write() {
+                warn!("error while writing cached response: {}", e);
+            }
+        }
+
+        Ok(entry.body.into_bytes())
+    }
+
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+        host: &str,
+    ) -> Result<reqwest::Response> {
+        let request = timeout(self.timeout, request.send());
+
+        let response = request
+            .await
+            .with_context(|| format!("Connection to {} timed-out", host))?
+            .with_context(|| format!("Unable to connect to {}", host))?;
+
+        Ok(response)
+    }
+
+    async fn read(&self, response: reqwest::Response, host: &str) -> Result<Vec<u8>> {
+        let bytes = timeout(self.timeout, response.bytes());
+
+        let bytes = bytes
+            .await
+            .with_context(|| format!("Connection to {} timed-out", host))?
+            .with_context(|| format!("Unable to connect to {}", host))?;
+
+        if log_enabled!(Level::Trace) {
+            trace!("received response: {}", std::str::from_utf8(&bytes)?);
+        }
+
+        Ok(bytes.to_vec())
+    }
+
+    fn find_http_cache_for(&self, key: &str) -> Result<std::path::PathBuf> {
+        if let Some(p) = WeatherClient::directories() {
+            let file = p
+                .cache_dir()
+                .join("results")
+                .join("http")
+                .join(format!("{}.json", key));
+            debug!("looking for cache file at '{}'", file.display());
+
+            if let Some(p) = file.parent() {
+                std::fs::create_dir_all(p)?;
+            }
+
+            Ok(file)
+        } else {
+            bail!("Count not locate project directory!");
+        }
+    }
+
     fn find_cache_for(
         &self,
+        provider: &dyn WeatherProvider,
         kind: QueryKind,
         location: &Location,
         language: Option<&str>,
@@ -197,6 +665,7 @@ impl WeatherClient {
                 QueryKind::Current => "api",
                 QueryKind::ForeCast => "oapi",
                 QueryKind::Pollution => "papi",
+                QueryKind::LocationName => "gapi",
             };
 
             let prefix2 = match units {
@@ -208,13 +677,24 @@ impl WeatherClient {
             let suffix = match location {
                 Location::LatLon(lat, lon) => format!("{}_{}", lat, lon),
                 Location::Place(p) => self.clean_up_for_path(p),
+                Location::Zip(code, country) => format!(
+                    "zip_{}_{}",
+                    self.clean_up_for_path(code),
+                    country.to_lowercase()
+                ),
+                Location::CityId(id) => format!("id_{}", id),
+            };
+            let prefix2 = match provider.cache_variant(kind) {
+                Some(variant) => format!("{}-{}", prefix2, variant),
+                None => prefix2.to_owned(),
             };
+
             let f = if let Some(lang) = language {
-                format!("results/{}{}-{}-{}.json", prefix, prefix2, lang, suffix)
+                format!("{}{}-{}-{}.json", prefix, prefix2, lang, suffix)
             } else {
-                format!("results/{}{}-{}.json", prefix, prefix2, suffix)
+                format!("{}{}-{}.json", prefix, prefix2, suffix)
             };
-            let file = p.cache_dir().join(f);
+            let file = p.cache_dir().join("results").join(provider.name()).join(f);
             debug!("looking for cache file at '{}'", file.display());
 
             if let Some(p) = file.parent() {
@@ -244,6 +724,7 @@ impl WeatherClient {
 
     fn query_cache(
         &self,
+        provider: &dyn WeatherProvider,
         kind: QueryKind,
         location: &Location,
         language: Option<&str>,
@@ -251,10 +732,10 @@ impl WeatherClient {
         offline: bool,
     ) -> Result<Option<Response>> {
         if offline {
-            let path = self.find_cache_for(kind, location, language, units)?;
+            let path = self.find_cache_for(provider, kind, location, language, units)?;
 
             if path.exists() {
-                return parse_cached_response(path.as_path(), kind, location);
+                return parse_cached_response(path.as_path(), location).map(Some);
             } else {
                 bail!(
                     "failed to find a cached response for '{}', but running offline",
@@ -262,13 +743,13 @@ impl WeatherClient {
                 );
             }
         } else if let Some(cache_length) = self.cache_length {
-            let path = self.find_cache_for(kind, location, language, units)?;
+            let path = self.find_cache_for(provider, kind, location, language, units)?;
 
             if path.exists() {
                 let m = std::fs::metadata(&path)?;
                 let elapsed = m.modified()?.elapsed()?;
                 if elapsed <= cache_length {
-                    return parse_cached_response(path.as_path(), kind, location);
+                    return parse_cached_response(path.as_path(), location).map(Some);
                 } else {
                     info!("ignoring expired cached response for {}", location);
                 }
@@ -280,34 +761,30 @@ impl WeatherClient {
 
     fn write_cache(
         &self,
+        provider: &dyn WeatherProvider,
         kind: QueryKind,
         location: &Location,
         language: Option<&str>,
         units: UnitMode,
-        bytes: &[u8],
+        response: &Response,
     ) -> Result<()> {
-        let path = self.find_cache_for(kind, location, language, units)?;
+        let path = self.find_cache_for(provider, kind, location, language, units)?;
         debug!("writing cache for {}", location);
-        std::fs::write(path, bytes)?;
+        std::fs::write(path, serde_json::to_vec(response)?)?;
 
         Ok(())
     }
 
     pub async fn query(
         &self,
+        provider: &dyn WeatherProvider,
         kind: QueryKind,
         location: &Location,
-        key: String,
         language: Option<&str>,
         units: UnitMode,
         offline: bool,
     ) -> Result<Response> {
-        // Adapt between locales and Openweather language codes:
-        // the codes OW accepts are a mix of ISO 639-1 language codes,
-        // ISO 3166 country codes and locale-like codes...
-        let language = language.map(make_openweather_language_codes);
-
-        match self.query_cache(kind, location, language.as_deref(), units, offline) {
+        match self.query_cache(provider, kind, location, language, units, offline) {
             Ok(Some(resp)) => return Ok(resp),
             Ok(None) => {}
             Err(e) => {
@@ -318,172 +795,161 @@ impl WeatherClient {
             }
         }
 
-        self.query_api(kind, location, key, language.as_deref(), units)
-            .await
+        let response = provider
+            .query(self, kind, location, language, units)
+            .await?;
+
+        if self.cache_length.is_some() {
+            if let Err(e) = self.write_cache(provider, kind, location, language, units, &response) {
+                warn!("error while writing cached response: {}", e);
+            }
+        }
+
+        Ok(response)
     }
 
-    async fn query_api(
+    /// Queries each provider in turn, until one of them answers.
+    ///
+    /// The next provider is only tried if the previous one doesn't support the query, timed
+    /// out, couldn't be reached or answered with a rate-limiting or server error (or had no
+    /// cached response when offline).
+    pub async fn query_first(
         &self,
+        providers: &[Box<dyn WeatherProvider>],
         kind: QueryKind,
         location: &Location,
-        key: String,
         language: Option<&str>,
         units: UnitMode,
+        offline: bool,
     ) -> Result<Response> {
-        debug!("querying {:?} with {:?} OpenWeather API", location, kind);
-        let mut params = Vec::with_capacity(3);
-        match location {
-            Location::LatLon(lat, lon) => {
-                params.push(("lat", lat.to_string()));
-                params.push(("lon", lon.to_string()));
+        let mut providers = providers.iter().peekable();
+        while let Some(provider) = providers.next() {
+            let provider = provider.as_ref();
+            match self
+                .query(provider, kind, location, language, units, offline)
+                .await
+            {
+                Ok(response) => {
+                    info!("got {:?} data from {}", kind, provider.name());
+                    return Ok(response);
+                }
+                Err(e) if offline || is_unsupported(&e) || is_transient(&e) => {
+                    match providers.peek() {
+                        Some(next) => {
+                            warn!("{} failed: {}; trying {}", provider.name(), e, next.name())
+                        }
+                        None => return Err(e),
+                    }
+                }
+                Err(e) => return Err(e),
             }
-            Location::Place(place) => params.push(("q", place.to_string())),
-        };
-
-        if let Some(language) = language {
-            params.push(("lang", language.to_owned()));
         }
 
-        params.push(("appid", key));
+        bail!("no weather provider to query");
+    }
+}
+
+/// A [`WriteColor`] counting the newlines written through it, to know how many lines to
+/// redraw.
+struct NewlineCounter<'a, W: WriteColor> {
+    inner: &'a mut W,
+    newlines: usize,
+}
 
-        params.push(("units", units.to_string()));
+impl<'a, W: WriteColor> NewlineCounter<'a, W> {
+    fn new(inner: &'a mut W) -> Self {
+        NewlineCounter { inner, newlines: 0 }
+    }
+}
 
-        let api_url = match kind {
-            QueryKind::Current => CURRENT_API_URL,
-            QueryKind::ForeCast => ONECALL_API_URL,
-            QueryKind::Pollution => POLLUTION_API_URL,
-        };
+impl<W: WriteColor> Write for NewlineCounter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.newlines += buf[..written].iter().filter(|&&b| b == b'\n').count();
+        Ok(written)
+    }
 
-        let request = self.client.get(api_url).query(&params).send();
-        let request = timeout(self.timeout, request);
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
 
-        let response = request
-            .await
-            .context("Connection to openweathermap.org timed-out")?
-            .context("Unable to connect to openweathermap.org")?;
+impl<W: WriteColor> WriteColor for NewlineCounter<'_, W> {
+    fn supports_color(&self) -> bool {
+        self.inner.supports_color()
+    }
 
-        let bytes = timeout(self.timeout, response.bytes());
+    fn set_color(&mut self, spec: &ColorSpec) -> std::io::Result<()> {
+        self.inner.set_color(spec)
+    }
 
-        let bytes = bytes
-            .await
-            .context("Connection to openweathermap.org timed-out")?
-            .context("Unable to connect to openweathermap.org")?;
+    fn reset(&mut self) -> std::io::Result<()> {
+        self.inner.reset()
+    }
+}
 
-        if log_enabled!(Level::Trace) {
-            trace!("received response: {}", std::str::from_utf8(&bytes)?);
+/// An error status returned by a weather service.
+#[derive(Debug)]
+pub struct StatusError {
+    pub host: String,
+    pub status: StatusCode,
+}
+
+impl StatusError {
+    pub fn new(host: &str, status: StatusCode) -> Self {
+        StatusError {
+            host: host.to_owned(),
+            status,
         }
+    }
+}
 
-        match kind {
-            QueryKind::Current => {
-                let resp: CResponse = serde_json::from_slice(&bytes)?;
-                match resp {
-                    CResponse::Success(w) => {
-                        if self.cache_length.is_some() {
-                            if let Err(e) =
-                                self.write_cache(kind, location, language, units, &bytes)
-                            {
-                                warn!("error while writing cached response: {}", e);
-                            }
-                        }
-                        Ok(Response::from_current(w))
-                    }
-                    CResponse::OtherInt { cod, message } => {
-                        handle_error(StatusCode::from_u16(cod)?, &message, location)
-                    }
-                    CResponse::OtherString { cod, message } => {
-                        handle_error(cod.parse()?, &message, location)
-                    }
-                }
-            }
-            QueryKind::ForeCast => {
-                let resp: OResponse = serde_json::from_slice(&bytes)?;
-                match resp {
-                    OResponse::Success(w) => {
-                        if self.cache_length.is_some() {
-                            if let Err(e) =
-                                self.write_cache(kind, location, language, units, &bytes)
-                            {
-                                warn!("error while writing cached response: {}", e);
-                            }
-                        }
-                        Ok(Response::from_forecast(*w))
-                    }
-                    OResponse::OtherInt { cod, message } => {
-                        handle_error(StatusCode::from_u16(cod)?, &message, location)
-                    }
-                    OResponse::OtherString { cod, message } => {
-                        handle_error(cod.parse()?, &message, location)
-                    }
-                }
-            }
-            QueryKind::Pollution => {
-                let resp: PResponse = serde_json::from_slice(&bytes)?;
-                match resp {
-                    PResponse::Success(p) => {
-                        if self.cache_length.is_some() {
-                            if let Err(e) =
-                                self.write_cache(kind, location, language, units, &bytes)
-                            {
-                                warn!("error while writing cached response: {}", e);
-                            }
-                        }
-                        Ok(Response::from_pollution(p))
-                    }
-                    PResponse::OtherInt { cod, message } => {
-                        handle_error(StatusCode::from_u16(cod)?, &message, location)
-                    }
-                    PResponse::OtherString { cod, message } => {
-                        handle_error(cod.parse()?, &message, location)
-                    }
-                }
-            },
+impl Display for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.status {
+            StatusCode::TOO_MANY_REQUESTS => write!(f, "Too many calls to {}!", self.host),
+            s => write!(f, "error from {}: {}", self.host, s),
         }
     }
 }
 
-fn parse_cached_response(
-    path: &Path,
-    kind: QueryKind,
-    location: &Location,
-) -> Result<Option<Response>, anyhow::Error> {
-    let f = std::fs::File::open(path)?;
-    Ok(match kind {
-        QueryKind::Current => {
-            if let CResponse::Success(resp) = serde_json::from_reader(f)? {
-                info!("using cached response for {}", location);
+impl std::error::Error for StatusError {}
 
-                Some(Response::from_current(resp))
-            } else {
-                None
-            }
+/// Whether an error is likely temporary, or specific to a provider, so that another one could
+/// answer instead.
+fn is_transient(e: &anyhow::Error) -> bool {
+    e.chain().any(|c| {
+        if c.is::<tokio::time::error::Elapsed>() {
+            return true;
         }
-        QueryKind::ForeCast => {
-            if let OResponse::Success(resp) = serde_json::from_reader(f)? {
-                info!("using cached response for {}", location);
-
-                Some(Response::from_forecast(*resp))
-            } else {
-                None
-            }
+        if let Some(e) = c.downcast_ref::<StatusError>() {
+            return e.status == StatusCode::TOO_MANY_REQUESTS || e.status.is_server_error();
         }
-        QueryKind::Pollution => {
-            if let PResponse::Success(resp) = serde_json::from_reader(f)? {
-                info!("using cached response for {}", location);
-
-                Some(Response::from_pollution(resp))
-            } else {
-                None
-            }
-        },
+        if let Some(e) = c.downcast_ref::<reqwest::Error>() {
+            return e.is_timeout() || e.is_connect();
+        }
+        false
     })
 }
 
-fn handle_error(error_code: StatusCode, message: &str, location: &Location) -> Result<Response> {
-    match error_code {
-        StatusCode::NOT_FOUND => bail!("location error: '{}' for '{}'", message, location),
-        StatusCode::TOO_MANY_REQUESTS => bail!("Too many calls to the API! If you not using your own API key, please get your own for free over at http://openweathermap.org"),
-        _ => bail!("error from OpenWeather API: {}: {}", error_code, message),
-    }
+/// Whether an error comes from a provider that can't answer this kind of query at all.
+fn is_unsupported(e: &anyhow::Error) -> bool {
+    e.chain().any(|c| c.is::<providers::Unsupported>())
+}
+
+#[derive(Deserialize, Serialize)]
+struct HttpCacheEntry {
+    expires: Option<i64>,
+    last_modified: Option<String>,
+    body: String,
+}
+
+fn parse_cached_response(path: &Path, location: &Location) -> Result<Response> {
+    let f = std::fs::File::open(path)?;
+    let resp = serde_json::from_reader(f)?;
+    info!("using cached response for {}", location);
+
+    Ok(resp)
 }
 
 #[derive(Clone, Copy, Debug, serde::Deserialize, serde::Serialize)]
@@ -494,14 +960,51 @@ pub enum DisplayMode {
     Ascii,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Terminal,
+    Json,
+    Waybar,
+    I3bar,
+    Polybar,
+    Tmux,
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// How to display the weather at several locations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MultiLocation {
+    /// One line per location.
+    Lines,
+    /// A single line, with the location before its segments.
+    Combined,
+}
+
+impl OutputFormat {
+    /// Whether the output is complete lines, rather than text meant to be embedded in
+    /// a line.
+    pub fn ends_with_newline(self) -> bool {
+        !matches!(
+            self,
+            OutputFormat::Terminal | OutputFormat::Bash | OutputFormat::Zsh | OutputFormat::Fish
+        )
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum QueryKind {
     Current,
     ForeCast,
     Pollution,
+    /// The name of the location, for coordinates, when the current weather isn't needed.
+    LocationName,
 }
 
-#[derive(Clone, Copy, Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum UnitMode {
     Standard,
@@ -521,26 +1024,6 @@ impl Display for UnitMode {
     }
 }
 
-fn make_openweather_language_codes(s: &str) -> Cow<str> {
-    // openweather supports these directly
-    if let "zh_CN" | "zh_TW" | "pt_BR" = s {
-        return s.to_lowercase().into();
-    };
-
-    let l_code = s.split_once('_').map(|t| t.0).unwrap_or(s);
-
-    // openweather uses country codes for those
-    match l_code {
-        "sq" => "al",        // Albanian
-        "cs" => "cz",        // Czech
-        "ko" => "kr",        // Korean
-        "lv" => "la",        // Latvian
-        "nb" | "nn" => "no", // Norwegian
-        s => s,
-    }
-    .into()
-}
-
 #[macro_export]
 macro_rules! show {
     ($level:ident, $($a:tt),*) => {
@@ -554,3 +1037,163 @@ macro_rules! show {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_coordinates() {
+        assert!(matches!(
+            Location::new("35.68,139.69"),
+            Location::LatLon(lat, lon) if lat == 35.68 && lon == 139.69
+        ));
+        assert!(matches!(
+            Location::new("48.85, 2.35"),
+            Location::LatLon(lat, lon) if lat == 48.85 && lon == 2.35
+        ));
+        assert!(matches!(
+            Location::new("-33.87,-151.21"),
+            Location::LatLon(lat, lon) if lat == -33.87 && lon == -151.21
+        ));
+    }
+
+    #[test]
+    fn parse_places() {
+        assert!(matches!(Location::new("London, UK"), Location::Place(p) if p == "London, UK"));
+        assert!(matches!(Location::new("Dubai"), Location::Place(p) if p == "Dubai"));
+        assert!(matches!(
+            Location::new("Paris, TX, US"),
+            Location::Place(p) if p == "Paris, TX, US"
+        ));
+        assert!(matches!(Location::new("auto"), Location::Place(p) if p == "auto"));
+    }
+
+    #[test]
+    fn parse_zip_codes() {
+        assert!(matches!(
+            Location::new("zip:75001,FR"),
+            Location::Zip(code, country) if code == "75001" && country == "FR"
+        ));
+        assert!(matches!(
+            Location::new("zip: 94040 , us"),
+            Location::Zip(code, country) if code == "94040" && country == "us"
+        ));
+        assert!(matches!(
+            Location::new("zip:10001"),
+            Location::Zip(code, country) if code == "10001" && country == "US"
+        ));
+    }
+
+    #[test]
+    fn parse_city_ids() {
+        assert!(matches!(
+            Location::new("id:2988507"),
+            Location::CityId(2988507)
+        ));
+        assert!(matches!(Location::new("id: 42"), Location::CityId(42)));
+        assert!(matches!(Location::new("id:paris"), Location::Place(p) if p == "id:paris"));
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for s in ["zip:75001,FR", "id:2988507", "London, UK", "35.68, 139.69"] {
+            assert_eq!(Location::new(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn resolve_aliases() {
+        let mut aliases = HashMap::new();
+        aliases.insert("home".to_owned(), Location::LatLon(48.85, 2.35));
+        aliases.insert(
+            "work".to_owned(),
+            Location::Zip("75001".into(), "FR".into()),
+        );
+
+        assert!(matches!(
+            Location::new("home").resolve(&aliases),
+            Location::LatLon(lat, lon) if *lat == 48.85 && *lon == 2.35
+        ));
+        assert!(matches!(
+            Location::new("work").resolve(&aliases),
+            Location::Zip(code, _) if code == "75001"
+        ));
+        assert!(matches!(
+            Location::new("Home").resolve(&aliases),
+            Location::Place(p) if p == "Home"
+        ));
+        // only names of places can be aliases
+        aliases.insert("1, 2".to_owned(), Location::Place("Dubai".into()));
+        assert!(matches!(
+            Location::new("1,2").resolve(&aliases),
+            Location::LatLon(..)
+        ));
+    }
+
+    #[test]
+    fn transient_status_errors() {
+        let error = |status| anyhow::Error::new(StatusError::new("example.com", status));
+
+        assert!(is_transient(&error(StatusCode::TOO_MANY_REQUESTS)));
+        assert!(is_transient(&error(StatusCode::INTERNAL_SERVER_ERROR)));
+        assert!(is_transient(&error(StatusCode::SERVICE_UNAVAILABLE)));
+        assert!(is_transient(
+            &error(StatusCode::BAD_GATEWAY).context("while querying the weather")
+        ));
+
+        assert!(!is_transient(&error(StatusCode::UNAUTHORIZED)));
+        assert!(!is_transient(&error(StatusCode::NOT_FOUND)));
+        assert!(!is_transient(&anyhow::anyhow!("invalid response")));
+    }
+
+    #[tokio::test]
+    async fn transient_timeouts() {
+        let elapsed = tokio::time::timeout(Duration::ZERO, std::future::pending::<()>())
+            .await
+            .unwrap_err();
+        let e = anyhow::Error::new(elapsed).context("while querying the weather");
+
+        assert!(is_transient(&e));
+        assert!(!is_unsupported(&e));
+    }
+
+    #[test]
+    fn unsupported_errors() {
+        let e = anyhow::Error::new(providers::Unsupported::new("NWS", "pollution"));
+        assert!(is_unsupported(&e));
+        assert!(is_unsupported(&e.context("while querying the weather")));
+        assert!(!is_transient(&anyhow::Error::new(
+            providers::Unsupported::new("NWS", "pollution")
+        )));
+
+        assert!(!is_unsupported(&anyhow::anyhow!(
+            "the NWS provider doesn't support pollution"
+        )));
+    }
+
+    #[test]
+    fn onecall_cache_paths() {
+        use providers::openweather::{OneCallVersion, OpenWeather};
+
+        let client = WeatherClient::new(None, Duration::from_secs(1));
+        let v2_5 = OpenWeather::new(String::new(), OneCallVersion::V2_5);
+        let v3_0 = OpenWeather::new(String::new(), OneCallVersion::V3_0);
+        let path = |provider: &OpenWeather, kind| {
+            let loc = Location::LatLon(48.85, 2.35);
+            client
+                .find_cache_for(provider, kind, &loc, None, UnitMode::Metric)
+                .unwrap()
+        };
+
+        assert_eq!(
+            path(&v2_5, QueryKind::Current),
+            path(&v3_0, QueryKind::Current)
+        );
+        assert_ne!(
+            path(&v2_5, QueryKind::ForeCast),
+            path(&v3_0, QueryKind::ForeCast)
+        );
+        assert!(path(&v2_5, QueryKind::ForeCast).ends_with("openweather/oapi-48.85_2.35.json"));
+    }
+}
@@ -1,22 +1,39 @@
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use clap::{CommandFactory, FromArgMatches, Parser};
 use env_logger::{Builder, Env};
 use girouette::{
-    cli::ProgramOptions, config::ProgramConfig, show, Girouette, Location, WeatherClient,
+    cli::{LocateOptions, ProgramCommand, ProgramOptions},
+    config::{DisplayConfig, GeolocationConfig, LocationSource, ProgramConfig},
+    providers::{
+        custom::Custom,
+        met_norway::MetNorway,
+        nws::Nws,
+        open_meteo::OpenMeteo,
+        openweather::{self, OpenWeather},
+        ProviderKind, WeatherProvider,
+    },
+    show, Girouette, Location, OutputFormat, WeatherClient,
 };
 use log::*;
 use std::{
+    collections::HashMap,
     env,
     ffi::{OsStr, OsString},
     path::{Path, PathBuf},
     time::Duration,
 };
 use termcolor::*;
-use tokio::runtime;
+use tokio::{runtime, sync::watch};
+
+#[cfg(unix)]
+use girouette::server::{self, Server};
 
 static DEFAULT_CONFIG: &str = include_str!("../config.yml");
 const DEFAULT_TIMEOUT_SEC: u64 = 10;
+const DEFAULT_INTERVAL_SEC: u64 = 300;
 const LOG_ENV_VAR: &str = "GIROUETTE_LOG";
+const NO_KEY: &str = "no API key for OpenWeather was found
+       you can get a key over at https://openweathermap.org/appid";
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let options = ProgramOptions::parse();
@@ -85,6 +102,16 @@ async fn run_async() -> Result<()> {
 
     let conf = make_config(&options)?;
 
+    if options.redraw {
+        let output = conf.display_config.output;
+        if output.ends_with_newline() {
+            bail!("--redraw is only supported with the terminal, bash, zsh and fish outputs");
+        }
+        if !atty::is(atty::Stream::Stdout) {
+            bail!("--redraw is only supported when writing to a terminal");
+        }
+    }
+
     let cache_length = match conf.cache.as_deref() {
         Some("none") | None => None,
         Some(c) => Some(
@@ -93,60 +120,412 @@ async fn run_async() -> Result<()> {
         ),
     };
 
-    let timeout = match conf.timeout {
-        Some(c) => humantime::parse_duration(&c)
-            .context("failed to parse timeout: not a valid duration")?,
+    let timeout = match conf.timeout.as_deref() {
+        Some(c) => {
+            humantime::parse_duration(c).context("failed to parse timeout: not a valid duration")?
+        }
 
         None => Duration::from_secs(DEFAULT_TIMEOUT_SEC),
     };
 
-    let location = match conf.location {
-        Some(Location::Place(l)) if l == "auto" => find_location(timeout).await?,
-        None => find_location(timeout).await?,
-        Some(loc) => loc,
-    };
+    if let Some(ProgramCommand::Locate(args)) = &options.command {
+        return locate(&options, &conf, args, timeout).await;
+    }
 
-    let mut key = conf.key.ok_or_else(|| {
-        anyhow!(
-            "no API key for OpenWeather was found
-       you can get a key over at https://openweathermap.org/appid",
-        )
-    })?;
+    if let Some(layout) = &options.client {
+        return run_client(&conf, layout, timeout).await;
+    }
 
-    if let Some('@') = key.chars().next() {
-        let key_os: OsString = key.into();
-        key = read_key(key_os.as_os_str())?;
+    let mut locations = Vec::new();
+    // indices of the locations found by geolocation
+    let mut auto = Vec::new();
+    for loc in &conf.location {
+        locations.push(match loc {
+            Location::Place(l) if l == "auto" => {
+                auto.push(locations.len());
+                find_location(&conf.geolocation, timeout, options.offline).await?
+            }
+            loc => loc.clone(),
+        });
     }
+    if locations.is_empty() {
+        auto.push(0);
+        locations.push(find_location(&conf.geolocation, timeout, options.offline).await?);
+    }
+
+    let interval = match conf.interval.as_deref() {
+        Some(c) => humantime::parse_duration(c)
+            .context("failed to parse interval: not a valid duration")?,
+        None => Duration::from_secs(DEFAULT_INTERVAL_SEC),
+    };
+
+    let providers = make_providers(&conf)?;
+    let output = conf.display_config.output;
+
+    // the main display options are the "default" layout of the server
+    let mut layouts = conf.layouts;
+    layouts
+        .entry("default".to_owned())
+        .or_insert_with(|| conf.display_config.clone());
+    let socket = conf.socket;
 
     let lib = Girouette::new(
         conf.display_config,
+        providers,
+        conf.locations,
         cache_length,
         timeout,
-        key,
         conf.language,
     );
 
+    if options.server {
+        if locations.len() > 1 {
+            warn!("the server only supports one location, ignoring all but the first one");
+        }
+        return run_server(
+            lib,
+            locations.swap_remove(0),
+            options.offline,
+            layouts,
+            interval,
+            timeout,
+            socket,
+        )
+        .await;
+    }
+
     let mut stdout = StandardStream::stdout(ColorChoice::Auto);
 
-    lib.display(&location, options.offline, &mut stdout).await
-}
+    if output == OutputFormat::I3bar || options.watch.is_some() {
+        let (sender, receiver) = watch::channel(locations.clone());
+        if !auto.is_empty()
+            && !options.offline
+            && conf.geolocation.sources.contains(&LocationSource::Geoclue)
+        {
+            follow_location(conf.geolocation, locations, auto, sender);
+        }
 
-#[cfg(feature = "geoclue")]
-async fn find_location(timeout: Duration) -> Result<Location> {
-    info!("no location to query, trying geoclue");
-    girouette::geoclue::get_location(timeout)
+        lib.watch(
+            receiver,
+            options.offline,
+            &mut stdout,
+            interval,
+            options.redraw,
+        )
         .await
-        .map_err(|e| {
-            e.context("geoclue couldn't report your location; use `-l/--location' argument`")
+    } else {
+        lib.display_all(&locations, options.offline, &mut stdout)
+            .await
+    }
+}
+
+async fn locate(
+    options: &ProgramOptions,
+    conf: &ProgramConfig,
+    args: &LocateOptions,
+    timeout: Duration,
+) -> Result<()> {
+    let key = read_conf_key(conf)?.ok_or_else(|| anyhow!(NO_KEY))?;
+    let client = WeatherClient::new(None, timeout);
+
+    let places = openweather::geocode(&client, &key, &args.query, args.limit).await?;
+    if places.is_empty() {
+        bail!("no place found for '{}'", args.query);
+    }
+
+    let names: Vec<_> = places
+        .iter()
+        .map(|p| {
+            let mut name = p.local_name(conf.language.as_deref()).to_owned();
+            if let Some(state) = &p.state {
+                name = format!("{}, {}", name, state);
+            }
+            format!("{}, {}", name, p.country)
         })
+        .collect();
+    for (i, (place, name)) in places.iter().zip(&names).enumerate() {
+        println!("{}. {} ({}, {})", i + 1, name, place.lat, place.lon);
+    }
+
+    if let Some(n) = args.save {
+        let i = n
+            .checked_sub(1)
+            .filter(|&i| i < places.len())
+            .ok_or_else(|| anyhow!("there is no place number {} in the list", n))?;
+        let path = config_path(options)?;
+        save_location(&path, &format!("{},{}", places[i].lat, places[i].lon))
+            .with_context(|| format!("failed to update config '{}'", path.display()))?;
+        println!(
+            "\nsaved {} as the location in '{}'",
+            names[i],
+            path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Sets the `location` of the config file at `path`, keeping the rest of the file as is.
+fn save_location(path: &Path, location: &str) -> Result<()> {
+    let content = if path.exists() {
+        std::fs::read_to_string(path)?
+    } else {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        DEFAULT_CONFIG.to_owned()
+    };
+
+    let new_line = format!("location: \"{}\"", location);
+    let mut lines = Vec::new();
+    let mut replaced = false;
+    let mut in_location = false;
+    for line in content.lines() {
+        if in_location {
+            // the previous value may go on over indented lines, or be a list
+            if line.starts_with(char::is_whitespace) || line.starts_with('-') {
+                continue;
+            }
+            in_location = false;
+        }
+        if !replaced && line.starts_with("location:") {
+            lines.push(new_line.clone());
+            replaced = true;
+            in_location = true;
+        } else {
+            lines.push(line.to_owned());
+        }
+    }
+    if !replaced {
+        lines.push(new_line);
+    }
+
+    std::fs::write(path, lines.join("\n") + "\n")?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn run_client(conf: &ProgramConfig, layout: &str, timeout: Duration) -> Result<()> {
+    use std::io::Write;
+
+    let socket = match &conf.socket {
+        Some(s) => s.clone(),
+        None => server::default_socket_path()?,
+    };
+
+    // the server might have to query each kind of data before answering
+    let output = server::query_server(&socket, layout, timeout * 3).await?;
+    std::io::stdout().write_all(&output)?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn run_client(_conf: &ProgramConfig, _layout: &str, _timeout: Duration) -> Result<()> {
+    bail!("the client/server mode is only supported on Unix systems")
+}
+
+#[cfg(unix)]
+async fn run_server(
+    lib: Girouette,
+    location: Location,
+    offline: bool,
+    layouts: HashMap<String, DisplayConfig>,
+    interval: Duration,
+    timeout: Duration,
+    socket: Option<PathBuf>,
+) -> Result<()> {
+    let socket = match socket {
+        Some(s) => s,
+        None => server::default_socket_path()?,
+    };
+
+    Server::new(lib, location, offline, layouts, interval, timeout)
+        .serve(&socket)
+        .await
+}
+
+#[cfg(not(unix))]
+async fn run_server(
+    _lib: Girouette,
+    _location: Location,
+    _offline: bool,
+    _layouts: HashMap<String, DisplayConfig>,
+    _interval: Duration,
+    _timeout: Duration,
+    _socket: Option<PathBuf>,
+) -> Result<()> {
+    bail!("the client/server mode is only supported on Unix systems")
+}
+
+async fn find_location(
+    conf: &GeolocationConfig,
+    timeout: Duration,
+    offline: bool,
+) -> Result<Location> {
+    let mut error = None;
+    for source in &conf.sources {
+        info!("no location to query, trying {}", source);
+        let res = match source {
+            LocationSource::Gpsd => girouette::gpsd::get_location(&conf.gpsd, timeout).await,
+            LocationSource::Geoclue => find_geoclue_location(conf, timeout).await,
+            LocationSource::Ip => find_ip_location(conf, timeout, offline).await,
+        };
+
+        match res {
+            Ok(loc) => return Ok(loc),
+            Err(e) => {
+                info!("{} couldn't report your location: {:#}", source, e);
+                error = Some(e);
+            }
+        }
+    }
+
+    let msg =
+        "couldn't find your location: set a location with '-l/--location' or in the config file";
+    Err(match error {
+        Some(e) => e.context(msg),
+        None => anyhow!(msg),
+    })
+}
+
+async fn find_ip_location(
+    conf: &GeolocationConfig,
+    timeout: Duration,
+    offline: bool,
+) -> Result<Location> {
+    let cache_length = humantime::parse_duration(&conf.ip_cache)
+        .context("failed to parse geolocation.ip_cache: not a valid duration")?;
+    let client = WeatherClient::new(None, timeout);
+
+    girouette::geoip::get_location(&client, &conf.ip_url, cache_length, offline).await
+}
+
+/// Updates the locations found by geolocation (at the `auto` indices) whenever geoclue
+/// reports that the user moved.
+#[cfg(feature = "geoclue")]
+fn follow_location(
+    conf: GeolocationConfig,
+    mut locations: Vec<Location>,
+    auto: Vec<usize>,
+    sender: watch::Sender<Vec<Location>>,
+) {
+    tokio::spawn(async move {
+        let mut tracker = match girouette::geoclue::Tracker::start(&conf, &locations[auto[0]]).await
+        {
+            Ok(t) => t,
+            Err(e) => {
+                info!(
+                    "not following the location, geoclue couldn't be started: {:#}",
+                    e
+                );
+                return;
+            }
+        };
+
+        loop {
+            match tracker.next_location().await {
+                Ok(loc) => {
+                    info!("location changed to {}", loc);
+                    for &i in &auto {
+                        locations[i] = loc.clone();
+                    }
+                    if sender.send(locations.clone()).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    warn!("stopped following the location: {:#}", e);
+                    return;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(not(feature = "geoclue"))]
+fn follow_location(
+    _conf: GeolocationConfig,
+    _locations: Vec<Location>,
+    _auto: Vec<usize>,
+    _sender: watch::Sender<Vec<Location>>,
+) {
+}
+
+#[cfg(feature = "geoclue")]
+async fn find_geoclue_location(conf: &GeolocationConfig, timeout: Duration) -> Result<Location> {
+    girouette::geoclue::get_location(conf, timeout).await
 }
 
 #[cfg(not(feature = "geoclue"))]
-async fn find_location(_timeout: Duration) -> Result<Location> {
-    use anyhow::bail;
+async fn find_geoclue_location(_conf: &GeolocationConfig, _timeout: Duration) -> Result<Location> {
+    bail!("built without geoclue support")
+}
+
+fn make_providers(conf: &ProgramConfig) -> Result<Vec<Box<dyn WeatherProvider>>> {
+    let kinds = match conf.provider.as_slice() {
+        [] => &[ProviderKind::OpenWeather],
+        kinds => kinds,
+    };
 
-    info!("no location to query, trying geoclue");
-    bail!("geolocalization unsupported: set a location with '-l/--location' or in the config file")
+    let mut providers: Vec<Box<dyn WeatherProvider>> = Vec::with_capacity(kinds.len());
+    for kind in kinds {
+        providers.push(make_provider(*kind, conf)?);
+    }
+
+    Ok(providers)
+}
+
+/// Creates a provider, reading the API key only if it needs one, so that a missing key file
+/// doesn't prevent using the other providers.
+fn make_provider(kind: ProviderKind, conf: &ProgramConfig) -> Result<Box<dyn WeatherProvider>> {
+    let provider: Box<dyn WeatherProvider> = match kind {
+        ProviderKind::OpenWeather => {
+            let key = read_conf_key(conf)?.ok_or_else(|| anyhow!(NO_KEY))?;
+
+            Box::new(OpenWeather::new(key, conf.onecall_version))
+        }
+        ProviderKind::OpenMeteo => Box::new(OpenMeteo::new()),
+        ProviderKind::MetNorway => Box::new(MetNorway::new()),
+        ProviderKind::Nws => Box::new(Nws::new()),
+        ProviderKind::Custom => {
+            let config = conf.custom_provider.clone().ok_or_else(|| {
+                anyhow!("the custom provider requires a `custom_provider` section in the config")
+            })?;
+            let key = if config.url.contains("{key}") {
+                read_conf_key(conf)?
+            } else {
+                None
+            };
+
+            Box::new(Custom::new(config, key))
+        }
+    };
+
+    Ok(provider)
+}
+
+/// The API key from the config, read from a file if it starts with '@'.
+fn read_conf_key(conf: &ProgramConfig) -> Result<Option<String>> {
+    match &conf.key {
+        Some(k) if k.starts_with('@') => {
+            let key_os: OsString = k.into();
+            Ok(Some(read_key(key_os.as_os_str())?))
+        }
+        key => Ok(key.clone()),
+    }
+}
+
+/// The config file given with "--config", or the default one.
+fn config_path(options: &ProgramOptions) -> Result<PathBuf> {
+    match &options.config {
+        Some(path) => Ok(path.clone()),
+        None => Ok(WeatherClient::directories()
+            .ok_or_else(|| anyhow!("failed to get project directories"))?
+            .config_dir()
+            .join("config.yml")),
+    }
 }
 
 fn make_config(options: &ProgramOptions) -> Result<ProgramConfig> {
@@ -191,20 +570,41 @@ fn make_config(options: &ProgramOptions) -> Result<ProgramConfig> {
     }
 
     //conf = set_conf_from_options(conf, &options.key, "key")?;
-    conf = set_conf_from_options(conf, &options.location, "location")?;
+    if !options.location.is_empty() {
+        conf = conf.set_override("location", options.location.clone())?;
+    }
     conf = set_conf_from_options(conf, &options.cache, "cache")?;
     conf = set_conf_from_options(conf, &options.language, "language")?;
     conf = set_conf_from_options(conf, &options.units, "units")?;
+    conf = set_conf_from_options(conf, &options.output, "output")?;
+    conf = set_conf_from_options(conf, &options.watch, "interval")?;
 
     if let Some(value) = &options.key {
         let actual_key = read_key(value.as_os_str())?;
         conf = conf.set_override("key", Some(actual_key))?
     }
 
-    let conf: ProgramConfig = conf.build()?.try_deserialize()?;
-    trace!("full config: {:#?}", conf);
+    let conf = conf.build()?;
+    let mut program: ProgramConfig = conf.clone().try_deserialize()?;
+
+    // layouts only override the main display options they set
+    let layouts: HashMap<String, config::Map<String, config::Value>> =
+        conf.get("layouts").unwrap_or_default();
+    for (name, keys) in layouts {
+        let mut layout = config::Config::builder().add_source(conf.clone());
+        for (key, value) in keys {
+            layout = layout.set_override(key, value)?;
+        }
+        let layout = layout
+            .build()?
+            .try_deserialize()
+            .with_context(|| format!("invalid layout '{}'", name))?;
+        program.layouts.insert(name, layout);
+    }
+
+    trace!("full config: {:#?}", program);
 
-    Ok(conf)
+    Ok(program)
 }
 
 fn read_key(s: &OsStr) -> Result<String> {
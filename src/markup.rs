@@ -0,0 +1,483 @@
+//! Translation of the styles used by segments into the markup of status bars and shell
+//! prompts.
+//!
+//! Segments render to a [`WriteColor`]; [`StyledText`] records what they write along
+//! with the style it is written in, instead of emitting ANSI escape codes.
+
+use std::io::{self, Write};
+use termcolor::{Ansi, Color, ColorSpec, WriteColor};
+
+/// Text written in a single style.
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub style: ColorSpec,
+    pub text: String,
+}
+
+/// A [`WriteColor`] recording styled text.
+///
+/// Styles are combined the way a terminal would: a [`ColorSpec`] without `reset` only
+/// overrides the attributes it sets. The `intense` attribute is folded into the colors
+/// (as the bright variants of the 16 basic colors).
+#[derive(Clone, Debug, Default)]
+pub struct StyledText {
+    spans: Vec<Span>,
+    current: ColorSpec,
+}
+
+impl StyledText {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The style the next text will be written in.
+    pub fn style(&self) -> &ColorSpec {
+        &self.current
+    }
+
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.iter().all(|s| s.text.is_empty())
+    }
+
+    /// The text without any style.
+    pub fn plain(&self) -> String {
+        self.spans.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    /// The text as Pango markup, as used by Waybar.
+    pub fn to_pango(&self) -> String {
+        let mut markup = String::new();
+        for span in &self.spans {
+            let mut attrs = String::new();
+            if let Some(fg) = span.style.fg() {
+                attrs.push_str(&format!(" foreground=\"{}\"", hex(fg)));
+            }
+            if let Some(bg) = span.style.bg() {
+                attrs.push_str(&format!(" background=\"{}\"", hex(bg)));
+            }
+            if span.style.bold() {
+                attrs.push_str(" weight=\"bold\"");
+            }
+            if span.style.italic() {
+                attrs.push_str(" style=\"italic\"");
+            }
+            if span.style.underline() {
+                attrs.push_str(" underline=\"single\"");
+            }
+            if span.style.dimmed() {
+                attrs.push_str(" alpha=\"50%\"");
+            }
+
+            let text = escape_pango(&span.text);
+            if attrs.is_empty() {
+                markup.push_str(&text);
+            } else {
+                markup.push_str(&format!("<span{}>{}</span>", attrs, text));
+            }
+        }
+        markup
+    }
+
+    /// The text with polybar/lemonbar formatting tags.
+    ///
+    /// Those bars only support colors and underlines; other attributes are ignored.
+    pub fn to_polybar(&self) -> String {
+        let mut markup = String::new();
+        let mut fg = None;
+        let mut bg = None;
+        let mut underline = false;
+        for span in &self.spans {
+            if span.style.fg() != fg.as_ref() {
+                fg = span.style.fg().copied();
+                match fg {
+                    Some(c) => markup.push_str(&format!("%{{F{}}}", hex(&c))),
+                    None => markup.push_str("%{F-}"),
+                }
+            }
+            if span.style.bg() != bg.as_ref() {
+                bg = span.style.bg().copied();
+                match bg {
+                    Some(c) => markup.push_str(&format!("%{{B{}}}", hex(&c))),
+                    None => markup.push_str("%{B-}"),
+                }
+            }
+            if span.style.underline() != underline {
+                underline = span.style.underline();
+                markup.push_str(if underline { "%{+u}" } else { "%{-u}" });
+            }
+
+            markup.push_str(&span.text.replace('%', "%%"));
+        }
+
+        if fg.is_some() {
+            markup.push_str("%{F-}");
+        }
+        if bg.is_some() {
+            markup.push_str("%{B-}");
+        }
+        if underline {
+            markup.push_str("%{-u}");
+        }
+        markup
+    }
+
+    /// The text with tmux style strings, as used in `status-left` and `status-right`.
+    pub fn to_tmux(&self) -> String {
+        let mut markup = String::new();
+        let mut style = &ColorSpec::new();
+        for span in &self.spans {
+            if &span.style != style {
+                style = &span.style;
+
+                let mut attrs = Vec::new();
+                if let Some(fg) = style.fg() {
+                    attrs.push(format!("fg={}", tmux_color(fg)));
+                }
+                if let Some(bg) = style.bg() {
+                    attrs.push(format!("bg={}", tmux_color(bg)));
+                }
+                for (set, attr) in [
+                    (style.bold(), "bold"),
+                    (style.italic(), "italics"),
+                    (style.underline(), "underscore"),
+                    (style.dimmed(), "dim"),
+                ] {
+                    if set {
+                        attrs.push(attr.to_owned());
+                    }
+                }
+
+                markup.push_str("#[default]");
+                if !attrs.is_empty() {
+                    markup.push_str(&format!("#[{}]", attrs.join(",")));
+                }
+            }
+
+            markup.push_str(&span.text.replace('#', "##"));
+        }
+
+        if style != &ColorSpec::new() {
+            markup.push_str("#[default]");
+        }
+        markup
+    }
+}
+
+impl Write for StyledText {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        match self.spans.last_mut() {
+            Some(last) if last.style == self.current => last.text.push_str(&text),
+            _ => self.spans.push(Span {
+                style: self.current.clone(),
+                text: text.into_owned(),
+            }),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WriteColor for StyledText {
+    fn supports_color(&self) -> bool {
+        true
+    }
+
+    fn set_color(&mut self, spec: &ColorSpec) -> io::Result<()> {
+        if spec.reset() {
+            self.current = ColorSpec::new();
+        }
+        if let Some(fg) = spec.fg() {
+            self.current
+                .set_fg(Some(with_intensity(*fg, spec.intense())));
+        }
+        if let Some(bg) = spec.bg() {
+            self.current
+                .set_bg(Some(with_intensity(*bg, spec.intense())));
+        }
+        if spec.bold() {
+            self.current.set_bold(true);
+        }
+        if spec.italic() {
+            self.current.set_italic(true);
+        }
+        if spec.underline() {
+            self.current.set_underline(true);
+        }
+        if spec.dimmed() {
+            self.current.set_dimmed(true);
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        self.current = ColorSpec::new();
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    /// The markers around text that doesn't take any space in a prompt.
+    fn zero_width_markers(self) -> (&'static str, &'static str) {
+        match self {
+            Shell::Bash => ("\\[", "\\]"),
+            Shell::Zsh => ("%{", "%}"),
+            // fish finds escape codes by itself
+            Shell::Fish => ("", ""),
+        }
+    }
+}
+
+/// A [`WriteColor`] writing ANSI escape codes between the zero-width markers of a shell,
+/// so that the escape codes don't count in the width of the prompt.
+pub struct PromptWriter<W: Write> {
+    inner: Ansi<W>,
+    shell: Shell,
+}
+
+impl<W: Write> PromptWriter<W> {
+    pub fn new(inner: W, shell: Shell) -> Self {
+        PromptWriter {
+            inner: Ansi::new(inner),
+            shell,
+        }
+    }
+
+    fn escaped(&mut self, f: impl FnOnce(&mut Ansi<W>) -> io::Result<()>) -> io::Result<()> {
+        let (start, end) = self.shell.zero_width_markers();
+        self.inner.get_mut().write_all(start.as_bytes())?;
+        f(&mut self.inner)?;
+        self.inner.get_mut().write_all(end.as_bytes())
+    }
+}
+
+impl<W: Write> Write for PromptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // the text itself must not be expanded by the shell
+        let text = String::from_utf8_lossy(buf);
+        let text = match self.shell {
+            Shell::Bash => text
+                .replace('\\', "\\\\")
+                .replace('$', "\\$")
+                .replace('`', "\\`"),
+            Shell::Zsh => text.replace('%', "%%"),
+            Shell::Fish => return self.inner.write(buf),
+        };
+        self.inner.write_all(text.as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> WriteColor for PromptWriter<W> {
+    fn supports_color(&self) -> bool {
+        true
+    }
+
+    fn set_color(&mut self, spec: &ColorSpec) -> io::Result<()> {
+        self.escaped(|w| w.set_color(spec))
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        self.escaped(|w| w.reset())
+    }
+}
+
+/// Index of a basic color in the 16-color palette.
+fn basic_index(color: Color) -> Option<u8> {
+    match color {
+        Color::Black => Some(0),
+        Color::Red => Some(1),
+        Color::Green => Some(2),
+        Color::Yellow => Some(3),
+        Color::Blue => Some(4),
+        Color::Magenta => Some(5),
+        Color::Cyan => Some(6),
+        Color::White => Some(7),
+        _ => None,
+    }
+}
+
+fn with_intensity(color: Color, intense: bool) -> Color {
+    match basic_index(color) {
+        Some(i) if intense => Color::Ansi256(i + 8),
+        _ => color,
+    }
+}
+
+/// Default xterm colors of the 16-color palette.
+const BASIC_COLORS: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+/// The RGB value of a color, using the xterm palette for indexed colors.
+pub fn rgb(color: Color) -> (u8, u8, u8) {
+    const CUBE_LEVELS: [u8; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+
+    match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Ansi256(i @ 0..=15) => BASIC_COLORS[i as usize],
+        Color::Ansi256(i @ 16..=231) => {
+            let i = i - 16;
+            (
+                CUBE_LEVELS[(i / 36) as usize],
+                CUBE_LEVELS[(i / 6 % 6) as usize],
+                CUBE_LEVELS[(i % 6) as usize],
+            )
+        }
+        Color::Ansi256(i) => {
+            let level = 8 + 10 * (i - 232);
+            (level, level, level)
+        }
+        c => BASIC_COLORS[basic_index(c).unwrap_or(7) as usize],
+    }
+}
+
+/// The color as `#rrggbb`.
+pub fn hex(color: &Color) -> String {
+    let (r, g, b) = rgb(*color);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// The color as understood by tmux: a name, `colourN` or `#rrggbb`.
+fn tmux_color(color: &Color) -> String {
+    match color {
+        Color::Black => "black".to_owned(),
+        Color::Red => "red".to_owned(),
+        Color::Green => "green".to_owned(),
+        Color::Yellow => "yellow".to_owned(),
+        Color::Blue => "blue".to_owned(),
+        Color::Magenta => "magenta".to_owned(),
+        Color::Cyan => "cyan".to_owned(),
+        Color::White => "white".to_owned(),
+        Color::Ansi256(i) => format!("colour{}", i),
+        c => hex(c),
+    }
+}
+
+fn escape_pango(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn styled(text: &str) -> StyledText {
+        let mut styled = StyledText::new();
+        styled
+            .set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))
+            .unwrap();
+        write!(styled, "{}", text).unwrap();
+        styled.reset().unwrap();
+        styled
+    }
+
+    fn prompt(shell: Shell, text: &str) -> String {
+        let mut out = Vec::new();
+        let mut writer = PromptWriter::new(&mut out, shell);
+        writer
+            .set_color(ColorSpec::new().set_fg(Some(Color::Red)))
+            .unwrap();
+        write!(writer, "{}", text).unwrap();
+        writer.reset().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn pango_escaping() {
+        assert_eq!(
+            styled("<b>rain & \"snow\"</b> 'o'").to_pango(),
+            "<span foreground=\"#cd0000\" weight=\"bold\">\
+             &lt;b&gt;rain &amp; &quot;snow&quot;&lt;/b&gt; &apos;o&apos;</span>"
+        );
+        assert_eq!(StyledText::new().to_pango(), "");
+    }
+
+    #[test]
+    fn polybar_escaping() {
+        assert_eq!(
+            styled("100% %{F#fff}").to_polybar(),
+            "%{F#cd0000}100%% %%{F#fff}%{F-}"
+        );
+    }
+
+    #[test]
+    fn intense_colors() {
+        let mut styled = StyledText::new();
+        styled
+            .set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_intense(true))
+            .unwrap();
+        write!(styled, "x").unwrap();
+        assert_eq!(styled.spans()[0].style.fg(), Some(&Color::Ansi256(9)));
+        assert_eq!(styled.to_polybar(), "%{F#ff0000}x%{F-}");
+    }
+
+    #[test]
+    fn tmux_escaping() {
+        assert_eq!(
+            styled("#[fg=red] #S").to_tmux(),
+            "#[default]#[fg=red,bold]##[fg=red] ##S#[default]"
+        );
+    }
+
+    #[test]
+    fn bash_escaping() {
+        assert_eq!(
+            prompt(Shell::Bash, "$(rm) `ls` \\"),
+            "\\[\x1b[0m\x1b[31m\\]\\$(rm) \\`ls\\` \\\\\\[\x1b[0m\\]"
+        );
+    }
+
+    #[test]
+    fn zsh_escaping() {
+        assert_eq!(
+            prompt(Shell::Zsh, "100% %F{red}"),
+            "%{\x1b[0m\x1b[31m%}100%% %%F{red}%{\x1b[0m%}"
+        );
+    }
+
+    #[test]
+    fn fish_is_not_escaped() {
+        assert_eq!(
+            prompt(Shell::Fish, "$x % \\"),
+            "\x1b[0m\x1b[31m$x % \\\x1b[0m"
+        );
+    }
+}
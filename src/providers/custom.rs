@@ -0,0 +1,432 @@
+use crate::{
+    api::{current::*, Coord, Rain, Response, Snow, Weather},
+    providers::{self, open_meteo, Unsupported, WeatherProvider, UNKNOWN_CONDITION},
+    Location, QueryKind, UnitMode, WeatherClient,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use chrono::DateTime;
+use log::*;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const NAME: &str = "custom";
+
+/// Everything but the unreserved characters of RFC 3986.
+const PLACEHOLDER_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Configuration of a user-defined provider, mapping an arbitrary JSON API onto
+/// the current weather data.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CustomProviderConfig {
+    /// URL to query, with `{lat}`, `{lon}`, `{key}` and `{units}` placeholders (replaced with
+    /// percent-encoded values).
+    pub url: String,
+    /// How to interpret the `weather_code` field.
+    pub weather_codes: WeatherCodes,
+    /// JSON pointers (e.g. `/data/0/temp`) to the fields of the response.
+    pub fields: FieldMapping,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum WeatherCodes {
+    #[serde(rename = "openweather")]
+    OpenWeather,
+    #[serde(rename = "wmo")]
+    Wmo,
+}
+
+impl Default for WeatherCodes {
+    fn default() -> Self {
+        WeatherCodes::OpenWeather
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FieldMapping {
+    pub temp: String,
+    pub feels_like: Option<String>,
+    pub temp_min: Option<String>,
+    pub temp_max: Option<String>,
+    pub pressure: Option<String>,
+    pub humidity: Option<String>,
+    pub wind_speed: Option<String>,
+    pub wind_deg: Option<String>,
+    pub wind_gust: Option<String>,
+    pub weather_code: Option<String>,
+    pub description: Option<String>,
+    pub clouds: Option<String>,
+    pub visibility: Option<String>,
+    pub rain: Option<String>,
+    pub snow: Option<String>,
+    pub sunrise: Option<String>,
+    pub sunset: Option<String>,
+    pub time: Option<String>,
+    pub timezone: Option<String>,
+    pub name: Option<String>,
+}
+
+/// A provider defined in the configuration file, for APIs girouette doesn't know about.
+///
+/// Only current weather data is supported, and values are expected to already be in the
+/// requested units.
+pub struct Custom {
+    config: CustomProviderConfig,
+    key: Option<String>,
+}
+
+impl Custom {
+    pub fn new(config: CustomProviderConfig, key: Option<String>) -> Self {
+        Custom { config, key }
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for Custom {
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    async fn query(
+        &self,
+        client: &WeatherClient,
+        kind: QueryKind,
+        location: &Location,
+        language: Option<&str>,
+        units: UnitMode,
+    ) -> Result<Response> {
+        debug!("querying {:?} with {:?} custom provider", location, kind);
+
+        match kind {
+            QueryKind::Current => {}
+            QueryKind::ForeCast => bail!(Unsupported::new(NAME, "forecasts")),
+            QueryKind::Pollution => bail!(Unsupported::new(NAME, "air quality data")),
+            QueryKind::LocationName => bail!(Unsupported::new(NAME, "reverse geocoding")),
+        }
+
+        let (lat, lon) = match location {
+            Location::LatLon(lat, lon) => (*lat, *lon),
+            Location::Place(p) => {
+                let place = open_meteo::geocode(client, p, language).await?;
+                (place.latitude, place.longitude)
+            }
+            Location::Zip(..) | Location::CityId(_) => {
+                bail!(Unsupported::new(NAME, "postal codes and city IDs"))
+            }
+        };
+
+        let mut url = self
+            .config
+            .url
+            .replace("{lat}", &encode(&lat.to_string()))
+            .replace("{lon}", &encode(&lon.to_string()))
+            .replace("{units}", &encode(&units.to_string()));
+        if url.contains("{key}") {
+            let key = self
+                .key
+                .as_deref()
+                .ok_or_else(|| anyhow!("no API key was found for the custom provider"))?;
+            url = url.replace("{key}", &encode(key));
+        }
+
+        let url = reqwest::Url::parse(&url)
+            .with_context(|| format!("invalid URL for the custom provider: '{}'", url))?;
+        let host = url.host_str().unwrap_or_default().to_owned();
+
+        let bytes = client.fetch(client.http().get(url), &host).await?;
+        let value: Value = serde_json::from_slice(&bytes)
+            .with_context(|| format!("invalid JSON response from {}", host))?;
+
+        let mapper = Mapper {
+            value: &value,
+            fields: &self.config.fields,
+        };
+        let timezone = match mapper.number(&self.config.fields.timezone) {
+            Some(t) => t as i32,
+            None => {
+                match open_meteo::utc_offset(client, &lat.to_string(), &lon.to_string()).await {
+                    Ok(offset) => offset,
+                    Err(e) => {
+                        // the mean solar time is better than nothing
+                        warn!("failed to get the time zone of {}: {}", location, e);
+                        (lon / 15.0).round() as i32 * 3600
+                    }
+                }
+            }
+        };
+        let current =
+            mapper.make_current(self.config.weather_codes, lat, lon, timezone, location)?;
+
+        Ok(Response::from_current(current))
+    }
+}
+
+fn encode(value: &str) -> String {
+    utf8_percent_encode(value, PLACEHOLDER_ENCODE_SET).to_string()
+}
+
+struct Mapper<'a> {
+    value: &'a Value,
+    fields: &'a FieldMapping,
+}
+
+impl<'a> Mapper<'a> {
+    fn get(&self, pointer: &Option<String>) -> Option<&'a Value> {
+        let pointer = pointer.as_deref()?;
+        let value = self.value.pointer(pointer).filter(|v| !v.is_null());
+        if value.is_none() {
+            debug!(
+                "no value at '{}' in the custom provider's response",
+                pointer
+            );
+        }
+        value
+    }
+
+    /// Numbers can also be given as strings (e.g. `"12.5"`).
+    fn number(&self, pointer: &Option<String>) -> Option<f32> {
+        match self.get(pointer)? {
+            Value::Number(n) => n.as_f64().map(|n| n as f32),
+            Value::String(s) => s.trim().parse().ok(),
+            v => {
+                warn!(
+                    "expected a number at '{}', found {}",
+                    pointer.as_deref()?,
+                    v
+                );
+                None
+            }
+        }
+    }
+
+    fn string(&self, pointer: &Option<String>) -> Option<String> {
+        match self.get(pointer)? {
+            Value::String(s) => Some(s.clone()),
+            v => Some(v.to_string()),
+        }
+    }
+
+    /// Times can be UNIX timestamps or RFC 3339 dates.
+    fn time(&self, pointer: &Option<String>) -> Option<i64> {
+        match self.get(pointer)? {
+            Value::Number(n) => n.as_i64(),
+            Value::String(s) => s
+                .parse()
+                .ok()
+                .or_else(|| DateTime::parse_from_rfc3339(s).ok().map(|d| d.timestamp())),
+            v => {
+                warn!("expected a time at '{}', found {}", pointer.as_deref()?, v);
+                None
+            }
+        }
+    }
+
+    fn make_current(
+        &self,
+        codes: WeatherCodes,
+        lat: f64,
+        lon: f64,
+        timezone: i32,
+        location: &Location,
+    ) -> Result<CurrentResponse> {
+        let f = self.fields;
+        let temp_pointer = Some(f.temp.clone());
+        let temp = self.number(&temp_pointer).ok_or_else(|| {
+            anyhow!(
+                "no temperature at '{}' in the custom provider's response",
+                f.temp
+            )
+        })?;
+
+        let description = self.string(&f.description);
+        let mut weather = match (codes, self.number(&f.weather_code)) {
+            (WeatherCodes::Wmo, Some(code)) => open_meteo::make_weather(Some(code as u8)),
+            (WeatherCodes::OpenWeather, Some(code)) => Weather {
+                id: code as u16,
+                main: String::new(),
+                description: None,
+                icon: String::new(),
+            },
+            (_, None) => Weather {
+                id: UNKNOWN_CONDITION,
+                main: "Unknown".to_owned(),
+                description: None,
+                icon: String::new(),
+            },
+        };
+        if description.is_some() {
+            weather.description = description;
+        }
+
+        let dt = self
+            .time(&f.time)
+            .unwrap_or_else(|| chrono::Utc::now().timestamp());
+        let (sunrise, sunset) = match (self.time(&f.sunrise), self.time(&f.sunset)) {
+            (Some(sunrise), Some(sunset)) => (Some(sunrise), Some(sunset)),
+            _ => match providers::sun_times(lat, lon, dt) {
+                Some((sunrise, sunset)) => (Some(sunrise), Some(sunset)),
+                None => (None, None),
+            },
+        };
+
+        Ok(CurrentResponse {
+            coord: Coord { lat, lon },
+            weather: vec![weather],
+            main: Main {
+                temp,
+                feels_like: self.number(&f.feels_like).unwrap_or(temp),
+                temp_min: self.number(&f.temp_min).unwrap_or(temp),
+                temp_max: self.number(&f.temp_max).unwrap_or(temp),
+                pressure: self.number(&f.pressure).map(|v| v.round() as u16),
+                humidity: self.number(&f.humidity).map(|v| v.round() as u8),
+            },
+            visibility: self.number(&f.visibility).map(|v| v as u16),
+            wind: self.number(&f.wind_speed).map(|speed| Wind {
+                speed,
+                deg: self.number(&f.wind_deg),
+                gale: self.number(&f.wind_gust),
+            }),
+            rain: self.number(&f.rain).map(|r| Rain {
+                one_h: Some(r),
+                three_h: None,
+            }),
+            snow: self.number(&f.snow).map(|s| Snow {
+                one_h: Some(s),
+                three_h: None,
+            }),
+            clouds: self.number(&f.clouds).map(|c| Clouds {
+                all: c.round() as u16,
+            }),
+            dt,
+            sys: Sys {
+                country: String::new(),
+                sunrise,
+                sunset,
+            },
+            timezone,
+            id: 0,
+            name: self.string(&f.name).unwrap_or_else(|| location.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(f: &[(&str, &str)]) -> FieldMapping {
+        let mut mapping = FieldMapping {
+            temp: "/main/temp".to_owned(),
+            ..Default::default()
+        };
+        for (name, pointer) in f {
+            let pointer = Some(pointer.to_string());
+            match *name {
+                "feels_like" => mapping.feels_like = pointer,
+                "humidity" => mapping.humidity = pointer,
+                "wind_speed" => mapping.wind_speed = pointer,
+                "wind_deg" => mapping.wind_deg = pointer,
+                "weather_code" => mapping.weather_code = pointer,
+                "description" => mapping.description = pointer,
+                "rain" => mapping.rain = pointer,
+                "sunrise" => mapping.sunrise = pointer,
+                "sunset" => mapping.sunset = pointer,
+                "time" => mapping.time = pointer,
+                "name" => mapping.name = pointer,
+                _ => panic!("unexpected field {}", name),
+            }
+        }
+        mapping
+    }
+
+    fn map(value: &Value, fields: &FieldMapping, codes: WeatherCodes) -> Result<CurrentResponse> {
+        Mapper { value, fields }.make_current(
+            codes,
+            48.85,
+            2.35,
+            7200,
+            &Location::LatLon(48.85, 2.35),
+        )
+    }
+
+    #[test]
+    fn field_paths() {
+        let value = json!({
+            "main": { "temp": 21.5, "humidity": "64.6" },
+            "wind": [{ "speed": 4.2, "dir": 270 }],
+            "station": { "name": "Paris-Montsouris" },
+            "rain": null,
+        });
+        let fields = fields(&[
+            ("humidity", "/main/humidity"),
+            ("wind_speed", "/wind/0/speed"),
+            ("wind_deg", "/wind/0/dir"),
+            ("rain", "/rain"),
+            ("name", "/station/name"),
+        ]);
+
+        let current = map(&value, &fields, WeatherCodes::OpenWeather).unwrap();
+        assert_eq!(current.main.temp, 21.5);
+        assert_eq!(current.main.feels_like, 21.5);
+        assert_eq!(current.main.humidity, Some(65));
+        let wind = current.wind.unwrap();
+        assert_eq!(wind.speed, 4.2);
+        assert_eq!(wind.deg, Some(270.0));
+        assert!(current.rain.is_none());
+        assert_eq!(current.name, "Paris-Montsouris");
+        assert_eq!(current.timezone, 7200);
+        assert_eq!(current.weather[0].id, UNKNOWN_CONDITION);
+    }
+
+    #[test]
+    fn times() {
+        let value = json!({
+            "main": { "temp": 12 },
+            "now": "2024-06-21T14:00:00+02:00",
+            "sun": { "rise": 1_718_941_620, "set": "1718999880" },
+        });
+        let fields = fields(&[
+            ("time", "/now"),
+            ("sunrise", "/sun/rise"),
+            ("sunset", "/sun/set"),
+        ]);
+
+        let current = map(&value, &fields, WeatherCodes::OpenWeather).unwrap();
+        assert_eq!(current.dt, 1_718_971_200);
+        assert_eq!(current.sys.sunrise, Some(1_718_941_620));
+        assert_eq!(current.sys.sunset, Some(1_718_999_880));
+    }
+
+    #[test]
+    fn weather_codes() {
+        let value = json!({ "main": { "temp": 12 }, "code": 3, "text": "grey" });
+        let fields = fields(&[("weather_code", "/code"), ("description", "/text")]);
+
+        let wmo = map(&value, &fields, WeatherCodes::Wmo).unwrap();
+        assert_eq!(wmo.weather[0].id, 804);
+        assert_eq!(wmo.weather[0].description.as_deref(), Some("grey"));
+
+        let openweather = map(&value, &fields, WeatherCodes::OpenWeather).unwrap();
+        assert_eq!(openweather.weather[0].id, 3);
+    }
+
+    #[test]
+    fn missing_temperature() {
+        let value = json!({ "main": { "temp": "n/a" } });
+        assert!(map(&value, &fields(&[]), WeatherCodes::OpenWeather).is_err());
+    }
+
+    #[test]
+    fn placeholders_are_encoded() {
+        assert_eq!(encode("-12.5"), "-12.5");
+        assert_eq!(encode("a&b=c d/é"), "a%26b%3Dc%20d%2F%C3%A9");
+    }
+}
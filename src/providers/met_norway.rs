@@ -0,0 +1,634 @@
+use crate::{
+    api::{current::*, one_call::*, Coord, Rain, Response, Snow, Weather},
+    providers::{self, open_meteo, Unsupported, WeatherProvider, UNKNOWN_CONDITION},
+    Location, QueryKind, UnitMode, WeatherClient,
+};
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, Utc};
+use log::*;
+use serde::Deserialize;
+
+const FORECAST_API_URL: &str = "https://api.met.no/weatherapi/locationforecast/2.0/complete";
+const SUN_API_URL: &str = "https://api.met.no/weatherapi/sunrise/3.0/sun";
+const HOST: &str = "api.met.no";
+const NAME: &str = "MET Norway";
+
+/// MET Norway (https://api.met.no), which doesn't require an API key.
+///
+/// The API doesn't provide location names, air quality or time zones: places are
+/// resolved with Open-Meteo's geocoding API, and time zones found with Open-Meteo too.
+pub struct MetNorway;
+
+impl MetNorway {
+    pub fn new() -> Self {
+        MetNorway
+    }
+}
+
+impl Default for MetNorway {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for MetNorway {
+    fn name(&self) -> &'static str {
+        "met_norway"
+    }
+
+    async fn query(
+        &self,
+        client: &WeatherClient,
+        kind: QueryKind,
+        location: &Location,
+        language: Option<&str>,
+        units: UnitMode,
+    ) -> Result<Response> {
+        debug!("querying {:?} with {:?} MET Norway API", location, kind);
+
+        match kind {
+            QueryKind::Pollution => {
+                bail!(Unsupported::new(NAME, "air quality data"))
+            }
+            QueryKind::LocationName => {
+                bail!(Unsupported::new(NAME, "reverse geocoding"))
+            }
+            _ => {}
+        }
+
+        let place = match location {
+            Location::LatLon(lat, lon) => open_meteo::Place {
+                name: location.to_string(),
+                latitude: *lat,
+                longitude: *lon,
+                country_code: None,
+            },
+            Location::Place(p) => open_meteo::geocode(client, p, language).await?,
+            Location::Zip(..) | Location::CityId(_) => {
+                bail!(Unsupported::new(NAME, "postal codes and city IDs"))
+            }
+        };
+
+        // the terms of service ask for at most 4 decimals, to make responses cacheable
+        let lat = format!("{:.4}", place.latitude);
+        let lon = format!("{:.4}", place.longitude);
+
+        let request = client
+            .http()
+            .get(FORECAST_API_URL)
+            .query(&[("lat", &lat), ("lon", &lon)]);
+        let bytes = client
+            .fetch_conditional(
+                request,
+                HOST,
+                &format!("met_norway-forecast-{}_{}", lat, lon),
+            )
+            .await?;
+        let resp: ForecastApiResponse = serde_json::from_slice(&bytes)?;
+
+        let offset = match open_meteo::utc_offset(client, &lat, &lon).await {
+            Ok(offset) => offset,
+            Err(e) => {
+                // the mean solar time is close enough to group forecasts by day
+                warn!("failed to get the time zone of {}: {}", location, e);
+                (place.longitude / 15.0).round() as i32 * 3600
+            }
+        };
+        let offset = FixedOffset::east_opt(offset)
+            .ok_or_else(|| anyhow!("invalid time zone offset: {}", offset))?;
+        let sun = query_sun(client, &lat, &lon, offset).await;
+
+        let conv = Converter { units, offset, sun };
+        match kind {
+            QueryKind::Current => Ok(Response::from_current(conv.make_current(resp, place)?)),
+            _ => Ok(Response::from_forecast(conv.make_forecast(resp, place)?)),
+        }
+    }
+}
+
+/// Finds today's sunrise and sunset, which aren't part of the forecast.
+async fn query_sun(
+    client: &WeatherClient,
+    lat: &str,
+    lon: &str,
+    offset: FixedOffset,
+) -> (Option<i64>, Option<i64>) {
+    let date = Utc::now().with_timezone(&offset).format("%F").to_string();
+    let request = client.http().get(SUN_API_URL).query(&[
+        ("lat", lat.to_owned()),
+        ("lon", lon.to_owned()),
+        ("date", date.clone()),
+        ("offset", offset.to_string()),
+    ]);
+    let key = format!("met_norway-sun-{}_{}-{}", lat, lon, date);
+
+    let resp = match client.fetch_conditional(request, HOST, &key).await {
+        Ok(bytes) => serde_json::from_slice::<SunApiResponse>(&bytes),
+        Err(e) => {
+            warn!("failed to get sunrise and sunset times: {}", e);
+            return (None, None);
+        }
+    };
+
+    match resp {
+        Ok(resp) => {
+            let time = |e: Option<SunEvent>| e.and_then(|e| e.time).and_then(|t| parse_time(&t));
+            (time(resp.properties.sunrise), time(resp.properties.sunset))
+        }
+        Err(e) => {
+            warn!("failed to parse sunrise and sunset times: {}", e);
+            (None, None)
+        }
+    }
+}
+
+fn parse_time(s: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(s)
+        .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%dT%H:%M%:z"))
+        .map(|d| d.timestamp())
+        .map_err(|e| debug!("invalid time '{}': {}", s, e))
+        .ok()
+}
+
+#[derive(Deserialize)]
+struct ForecastApiResponse {
+    properties: Properties,
+}
+
+#[derive(Deserialize)]
+struct Properties {
+    timeseries: Vec<TimeStep>,
+}
+
+#[derive(Deserialize)]
+struct TimeStep {
+    time: String,
+    data: StepData,
+}
+
+#[derive(Deserialize)]
+struct StepData {
+    instant: InstantData,
+    next_1_hours: Option<Period>,
+    next_6_hours: Option<Period>,
+    next_12_hours: Option<Period>,
+}
+
+#[derive(Deserialize)]
+struct InstantData {
+    details: InstantDetails,
+}
+
+#[derive(Deserialize)]
+struct InstantDetails {
+    air_pressure_at_sea_level: Option<f32>,
+    air_temperature: Option<f32>,
+    cloud_area_fraction: Option<f32>,
+    relative_humidity: Option<f32>,
+    wind_from_direction: Option<f32>,
+    wind_speed: Option<f32>,
+    wind_speed_of_gust: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct Period {
+    summary: Option<Summary>,
+    #[serde(default)]
+    details: PeriodDetails,
+}
+
+#[derive(Deserialize)]
+struct Summary {
+    symbol_code: String,
+}
+
+#[derive(Deserialize, Default)]
+struct PeriodDetails {
+    air_temperature_max: Option<f32>,
+    air_temperature_min: Option<f32>,
+    precipitation_amount: Option<f32>,
+    probability_of_precipitation: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct SunApiResponse {
+    properties: SunProperties,
+}
+
+#[derive(Deserialize)]
+struct SunProperties {
+    sunrise: Option<SunEvent>,
+    sunset: Option<SunEvent>,
+}
+
+#[derive(Deserialize)]
+struct SunEvent {
+    time: Option<String>,
+}
+
+impl StepData {
+    fn symbol(&self) -> Option<&str> {
+        [&self.next_1_hours, &self.next_6_hours, &self.next_12_hours]
+            .iter()
+            .filter_map(|p| p.as_ref().and_then(|p| p.summary.as_ref()))
+            .map(|s| s.symbol_code.as_str())
+            .next()
+    }
+
+    fn precipitation(&self) -> Option<f32> {
+        self.next_1_hours
+            .as_ref()
+            .and_then(|p| p.details.precipitation_amount)
+    }
+
+    fn pop(&self) -> Option<f32> {
+        self.next_1_hours
+            .as_ref()
+            .or(self.next_6_hours.as_ref())
+            .and_then(|p| p.details.probability_of_precipitation)
+            .map(|p| p / 100.0)
+    }
+}
+
+struct Converter {
+    units: UnitMode,
+    offset: FixedOffset,
+    sun: (Option<i64>, Option<i64>),
+}
+
+impl Converter {
+    fn temp(&self, celsius: f32) -> f32 {
+        providers::temperature(celsius, self.units)
+    }
+
+    fn speed(&self, meters_per_sec: f32) -> f32 {
+        providers::speed(meters_per_sec, self.units)
+    }
+
+    fn steps(&self, resp: ForecastApiResponse) -> Result<Vec<(i64, StepData)>> {
+        let steps: Vec<_> = resp
+            .properties
+            .timeseries
+            .into_iter()
+            .filter_map(|s| parse_time(&s.time).map(|t| (t, s.data)))
+            .collect();
+
+        if steps.is_empty() {
+            bail!("empty forecast in MET Norway response");
+        }
+
+        Ok(steps)
+    }
+
+    fn make_current(
+        &self,
+        resp: ForecastApiResponse,
+        place: open_meteo::Place,
+    ) -> Result<CurrentResponse> {
+        let steps = self.steps(resp)?;
+        let today = self.day_of(steps[0].0);
+        let (dt, data) = &steps[0];
+        let d = &data.instant.details;
+
+        let temps = steps
+            .iter()
+            .filter(|(t, _)| self.day_of(*t) == today)
+            .filter_map(|(_, s)| s.instant.details.air_temperature);
+        let temp = d.air_temperature.unwrap_or_default();
+        let (temp_min, temp_max) = temps
+            .map(|t| (t, t))
+            .reduce(|(min, max), (t, _)| (min.min(t), max.max(t)))
+            .unwrap_or((temp, temp));
+
+        let symbol = data.symbol();
+        let precipitation = data.precipitation().filter(|p| *p > 0.0);
+        let snowing = is_snow(symbol);
+
+        Ok(CurrentResponse {
+            coord: Coord {
+                lat: place.latitude,
+                lon: place.longitude,
+            },
+            weather: vec![make_weather(symbol)],
+            main: Main {
+                temp: self.temp(temp),
+                feels_like: self.temp(apparent_temperature(d)),
+                temp_min: self.temp(temp_min.min(temp)),
+                temp_max: self.temp(temp_max.max(temp)),
+                pressure: d.air_pressure_at_sea_level.map(|v| v.round() as u16),
+                humidity: d.relative_humidity.map(|v| v.round() as u8),
+            },
+            visibility: None,
+            wind: d.wind_speed.map(|speed| Wind {
+                speed: self.speed(speed),
+                deg: d.wind_from_direction,
+                gale: d.wind_speed_of_gust.map(|g| self.speed(g)),
+            }),
+            rain: precipitation.filter(|_| !snowing).map(|r| Rain {
+                one_h: Some(r),
+                three_h: None,
+            }),
+            snow: precipitation.filter(|_| snowing).map(|s| Snow {
+                one_h: Some(s),
+                three_h: None,
+            }),
+            clouds: d.cloud_area_fraction.map(|c| Clouds {
+                all: c.round() as u16,
+            }),
+            dt: *dt,
+            sys: Sys {
+                country: place.country_code.unwrap_or_default(),
+                sunrise: self.sun.0,
+                sunset: self.sun.1,
+            },
+            timezone: self.offset.local_minus_utc(),
+            id: 0,
+            name: place.name,
+        })
+    }
+
+    fn make_forecast(
+        &self,
+        resp: ForecastApiResponse,
+        place: open_meteo::Place,
+    ) -> Result<OneCallResponse> {
+        let steps = self.steps(resp)?;
+
+        let hourly = steps
+            .iter()
+            .filter(|(_, s)| s.next_1_hours.is_some())
+            .map(|(t, s)| self.make_hour(*t, s))
+            .collect();
+
+        // group the time steps by local day, starting with today
+        let mut days: Vec<Vec<&(i64, StepData)>> = Vec::new();
+        for step in &steps {
+            match days.last_mut() {
+                Some(day) if self.day_of(day[0].0) == self.day_of(step.0) => day.push(step),
+                _ => days.push(vec![step]),
+            }
+        }
+        let daily = days.iter().filter_map(|d| self.make_day(d)).collect();
+
+        let mut current = self.make_hour(steps[0].0, &steps[0].1);
+        current.sunrise = self.sun.0;
+        current.sunset = self.sun.1;
+
+        Ok(OneCallResponse {
+            lat: place.latitude as f32,
+            lon: place.longitude as f32,
+            timezone_offset: self.offset.local_minus_utc(),
+            current,
+            minutely: None,
+            hourly: Some(hourly),
+            daily: Some(daily),
+            alerts: None,
+            overview: None,
+        })
+    }
+
+    fn make_hour(&self, dt: i64, s: &StepData) -> WeatherData {
+        let d = &s.instant.details;
+        let symbol = s.symbol();
+        let precipitation = s.precipitation();
+        let snowing = is_snow(symbol);
+
+        WeatherData {
+            dt,
+            sunrise: None,
+            sunset: None,
+            temp: Temperature::Value(self.temp(d.air_temperature.unwrap_or_default())),
+            feels_like: FeelsLike::Value(self.temp(apparent_temperature(d))),
+            pressure: d.air_pressure_at_sea_level.map(|v| v.round() as u16),
+            humidity: d.relative_humidity.map(|v| v.round() as u8),
+            clouds: d.cloud_area_fraction.map(|v| v.round() as u16),
+            visibility: None,
+            wind_speed: self.speed(d.wind_speed.unwrap_or_default()),
+            wind_deg: d.wind_from_direction,
+            wind_gust: d.wind_speed_of_gust.map(|g| self.speed(g)),
+            pop: s.pop(),
+            rain: precipitation.filter(|_| !snowing).map(RainResult::Value),
+            snow: precipitation.filter(|_| snowing).map(SnowResult::Value),
+            weather: vec![make_weather(symbol)],
+            summary: None,
+        }
+    }
+
+    /// The forecast for a day, or `None` if there is no temperature for that day.
+    fn make_day(&self, steps: &[&(i64, StepData)]) -> Option<WeatherData> {
+        fn details(s: &(i64, StepData)) -> &InstantDetails {
+            &s.1.instant.details
+        }
+        let temps = steps
+            .iter()
+            .filter_map(|s| details(s).air_temperature)
+            .chain(
+                steps
+                    .iter()
+                    .filter_map(|s| s.1.next_6_hours.as_ref())
+                    .flat_map(|p| {
+                        vec![p.details.air_temperature_min, p.details.air_temperature_max]
+                    })
+                    .flatten(),
+            );
+        let (min, max) = match temps
+            .map(|t| (t, t))
+            .reduce(|(min, max), (t, _)| (min.min(t), max.max(t)))
+        {
+            Some(range) => range,
+            None => {
+                debug!("no temperature for the day of {}; skipping it", steps[0].0);
+                return None;
+            }
+        };
+
+        // the step closest to a given local hour of the day
+        let at_hour = |hour: i64| {
+            steps
+                .iter()
+                .min_by_key(|(t, _)| (self.local_hour(*t) - hour).abs())
+                .copied()
+        };
+        let temp_at = |hour, fallback| {
+            at_hour(hour)
+                .and_then(|s| details(s).air_temperature)
+                .unwrap_or(fallback)
+        };
+        let feels_at = |hour, fallback| {
+            at_hour(hour)
+                .map(|s| apparent_temperature(details(s)))
+                .unwrap_or(fallback)
+        };
+
+        let noon = at_hour(12).unwrap_or(steps[0]);
+        let windiest = steps
+            .iter()
+            .max_by(|a, b| {
+                let a = details(a).wind_speed.unwrap_or_default();
+                let b = details(b).wind_speed.unwrap_or_default();
+                a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .copied()
+            .unwrap_or(noon);
+
+        // 6-hour periods start at 00, 06, 12 and 18 UTC, and hourly steps overlap them
+        let precipitation: f32 = steps
+            .iter()
+            .filter(|(t, _)| t % (6 * 3600) == 0)
+            .filter_map(|(_, s)| s.next_6_hours.as_ref())
+            .filter_map(|p| p.details.precipitation_amount)
+            .fold(0.0, |acc, p| acc + p);
+        let pop = steps
+            .iter()
+            .filter_map(|(_, s)| s.pop())
+            .fold(None, |acc: Option<f32>, p| {
+                Some(acc.map_or(p, |a| a.max(p)))
+            });
+
+        let symbol = noon.1.symbol();
+        let snowing = is_snow(symbol);
+        let noon_details = details(noon);
+
+        Some(WeatherData {
+            dt: noon.0,
+            sunrise: None,
+            sunset: None,
+            temp: Temperature::Values(TempValues {
+                morn: self.temp(temp_at(9, min)),
+                day: self.temp(temp_at(15, max)),
+                eve: self.temp(temp_at(19, max)),
+                night: self.temp(temp_at(23, min)),
+                min: self.temp(min),
+                max: self.temp(max),
+            }),
+            feels_like: FeelsLike::Values(FeelsLikeValues {
+                morn: self.temp(feels_at(9, min)),
+                day: self.temp(feels_at(15, max)),
+                eve: self.temp(feels_at(19, max)),
+                night: self.temp(feels_at(23, min)),
+            }),
+            pressure: noon_details
+                .air_pressure_at_sea_level
+                .map(|v| v.round() as u16),
+            humidity: noon_details.relative_humidity.map(|v| v.round() as u8),
+            clouds: noon_details.cloud_area_fraction.map(|v| v.round() as u16),
+            visibility: None,
+            wind_speed: self.speed(details(windiest).wind_speed.unwrap_or_default()),
+            wind_deg: details(windiest).wind_from_direction,
+            wind_gust: details(windiest).wind_speed_of_gust.map(|g| self.speed(g)),
+            pop,
+            rain: Some(precipitation)
+                .filter(|p| *p > 0.0 && !snowing)
+                .map(RainResult::Value),
+            snow: Some(precipitation)
+                .filter(|p| *p > 0.0 && snowing)
+                .map(SnowResult::Value),
+            weather: vec![make_weather(symbol)],
+            summary: None,
+        })
+    }
+
+    fn day_of(&self, t: i64) -> i64 {
+        providers::day_of(t, self.offset)
+    }
+
+    fn local_hour(&self, t: i64) -> i64 {
+        providers::local_hour(t, self.offset)
+    }
+}
+
+fn apparent_temperature(d: &InstantDetails) -> f32 {
+    providers::apparent_temperature(
+        d.air_temperature.unwrap_or_default(),
+        d.relative_humidity,
+        d.wind_speed,
+    )
+}
+
+fn is_snow(symbol: Option<&str>) -> bool {
+    symbol.map_or(false, |s| s.contains("snow"))
+}
+
+fn make_weather(symbol: Option<&str>) -> Weather {
+    let (id, main, description) = match symbol {
+        Some(symbol) => symbol_to_openweather(symbol),
+        None => (UNKNOWN_CONDITION, "Unknown", "unknown".to_owned()),
+    };
+
+    Weather {
+        id,
+        main: main.to_owned(),
+        description: Some(description),
+        icon: String::new(),
+    }
+}
+
+/// Maps MET Norway weather symbols (e.g. "lightrainshowers_day") to OpenWeather conditions.
+fn symbol_to_openweather(symbol: &str) -> (u16, &'static str, String) {
+    // the day/night/polartwilight variants only differ by their icon
+    let base = symbol.split('_').next().unwrap_or(symbol);
+
+    let simple = match base {
+        "clearsky" => Some((800, "Clear", "clear sky")),
+        "fair" => Some((801, "Clouds", "fair")),
+        "partlycloudy" => Some((802, "Clouds", "partly cloudy")),
+        "cloudy" => Some((804, "Clouds", "cloudy")),
+        "fog" => Some((741, "Fog", "fog")),
+        _ => None,
+    };
+    if let Some((id, main, description)) = simple {
+        return (id, main, description.to_owned());
+    }
+
+    // everything else is "[light|heavy]<rain|sleet|snow>[showers][andthunder]"
+    let (intensity, rest) = if let Some(r) = base.strip_prefix("light") {
+        // some symbols are misspelled "lights..." in the API
+        let r = match r.strip_prefix('s') {
+            Some(r2) if r2.starts_with("sleet") || r2.starts_with("snow") => r2,
+            _ => r,
+        };
+        (0, r)
+    } else if let Some(r) = base.strip_prefix("heavy") {
+        (2, r)
+    } else {
+        (1, base)
+    };
+    let (thunder, rest) = match rest.strip_suffix("andthunder") {
+        Some(r) => (true, r),
+        None => (false, rest),
+    };
+    let (showers, kind) = match rest.strip_suffix("showers") {
+        Some(r) => (true, r),
+        None => (false, rest),
+    };
+
+    let (id, main) = match (kind, showers, thunder) {
+        (_, _, true) => (200 + intensity, "Thunderstorm"),
+        ("rain", true, _) => (520 + intensity, "Rain"),
+        ("rain", false, _) => (500 + intensity, "Rain"),
+        ("sleet", _, _) => (613, "Snow"),
+        ("snow", true, _) => (620 + intensity, "Snow"),
+        ("snow", false, _) => (600 + intensity, "Snow"),
+        _ => {
+            debug!("unknown MET Norway weather symbol: {}", symbol);
+            return (UNKNOWN_CONDITION, "Unknown", "unknown".to_owned());
+        }
+    };
+
+    let mut description = match intensity {
+        0 => "light ".to_owned(),
+        2 => "heavy ".to_owned(),
+        _ => String::new(),
+    };
+    description.push_str(kind);
+    if showers {
+        description.push_str(" showers");
+    }
+    if thunder {
+        description.push_str(" and thunder");
+    }
+
+    (id, main, description)
+}
@@ -0,0 +1,211 @@
+pub mod custom;
+pub mod met_norway;
+pub mod nws;
+pub mod open_meteo;
+pub mod openweather;
+
+use crate::{api::Response, Location, QueryKind, UnitMode, WeatherClient};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{FixedOffset, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// A source of weather data.
+///
+/// Providers translate whatever their backend returns into a [`Response`], so that
+/// every segment can be displayed regardless of where the data comes from. In particular,
+/// weather conditions are mapped to the closest OpenWeather condition, so that they use the
+/// same icons.
+#[async_trait]
+pub trait WeatherProvider: Send + Sync {
+    /// Name of the provider, used in logs and to separate cached responses.
+    fn name(&self) -> &'static str;
+
+    /// Tells apart cached responses to the same query that the provider answers differently
+    /// depending on its configuration (e.g. the version of the API).
+    fn cache_variant(&self, _kind: QueryKind) -> Option<&'static str> {
+        None
+    }
+
+    /// Queries the provider for one kind of weather data at the given location.
+    async fn query(
+        &self,
+        client: &WeatherClient,
+        kind: QueryKind,
+        location: &Location,
+        language: Option<&str>,
+        units: UnitMode,
+    ) -> Result<Response>;
+}
+
+/// The error returned by a provider asked for data, or a kind of location, it can't handle.
+///
+/// In a chain of providers, the next one is always queried after this error.
+#[derive(Debug)]
+pub struct Unsupported {
+    pub provider: &'static str,
+    pub what: &'static str,
+}
+
+impl Unsupported {
+    pub fn new(provider: &'static str, what: &'static str) -> Self {
+        Unsupported { provider, what }
+    }
+}
+
+impl Display for Unsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the {} provider doesn't support {}",
+            self.provider, self.what
+        )
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// The condition id given to weather that can't be mapped to an OpenWeather condition.
+///
+/// It isn't a valid OpenWeather id, so that segments show the weather as unknown instead of,
+/// say, a clear sky.
+pub const UNKNOWN_CONDITION: u16 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ProviderKind {
+    #[serde(rename = "openweather")]
+    OpenWeather,
+    #[serde(rename = "open_meteo")]
+    OpenMeteo,
+    #[serde(rename = "met_norway")]
+    MetNorway,
+    #[serde(rename = "nws")]
+    Nws,
+    #[serde(rename = "custom")]
+    Custom,
+}
+
+impl Default for ProviderKind {
+    fn default() -> Self {
+        ProviderKind::OpenWeather
+    }
+}
+
+/// Converts a temperature from Celsius to the given units.
+pub(crate) fn temperature(celsius: f32, units: UnitMode) -> f32 {
+    match units {
+        UnitMode::Standard => celsius + 273.15,
+        UnitMode::Metric => celsius,
+        UnitMode::Imperial => celsius * 1.8 + 32.0,
+    }
+}
+
+/// Converts a speed from meters per second to the given units (miles per hour in imperial
+/// units).
+pub(crate) fn speed(meters_per_sec: f32, units: UnitMode) -> f32 {
+    match units {
+        UnitMode::Imperial => meters_per_sec * 2.236_936,
+        _ => meters_per_sec,
+    }
+}
+
+/// The day of a UNIX timestamp in the time zone at `offset`, as a number of days since the
+/// epoch, to group forecasts by day.
+pub(crate) fn day_of(t: i64, offset: FixedOffset) -> i64 {
+    (t + offset.local_minus_utc() as i64).div_euclid(86400)
+}
+
+/// The hour of a UNIX timestamp in the time zone at `offset`.
+pub(crate) fn local_hour(t: i64, offset: FixedOffset) -> i64 {
+    offset.timestamp(t, 0).hour() as i64
+}
+
+/// Estimates the felt temperature (in Celsius) for providers that don't give it, from the
+/// relative humidity (in %) and the wind speed (in m/s).
+///
+/// This uses the Australian Bureau of Meteorology formula.
+pub(crate) fn apparent_temperature(
+    temp: f32,
+    humidity: Option<f32>,
+    wind_speed: Option<f32>,
+) -> f32 {
+    match (humidity, wind_speed) {
+        (Some(rh), Some(ws)) => {
+            let e = rh / 100.0 * 6.105 * (17.27 * temp / (237.7 + temp)).exp();
+            temp + 0.33 * e - 0.70 * ws - 4.00
+        }
+        _ => temp,
+    }
+}
+
+/// Estimates the sunrise and sunset times (as UNIX timestamps) of the day of `dt` at the given
+/// coordinates, for providers that don't give them.
+///
+/// This uses the sunrise equation, which is precise to a minute or two. There is no sunrise nor
+/// sunset during polar days and nights.
+pub(crate) fn sun_times(lat: f64, lon: f64, dt: i64) -> Option<(i64, i64)> {
+    const J2000: f64 = 2_451_545.0;
+    const UNIX_EPOCH: f64 = 2_440_587.5;
+
+    // mean solar noon of the local day, in days since J2000
+    let julian_day = dt as f64 / 86400.0 + UNIX_EPOCH;
+    let noon = (julian_day - J2000 + lon / 360.0).round() - lon / 360.0;
+
+    let anomaly = (357.5291 + 0.985_600_28 * noon)
+        .rem_euclid(360.0)
+        .to_radians();
+    let center =
+        1.9148 * anomaly.sin() + 0.02 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let longitude = (anomaly.to_degrees() + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit = J2000 + noon + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * longitude).sin();
+
+    let declination = (longitude.sin() * 23.4397f64.to_radians().sin()).asin();
+    let lat = lat.to_radians();
+    let cos_hour_angle = ((-0.833f64).to_radians().sin() - lat.sin() * declination.sin())
+        / (lat.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees() / 360.0;
+
+    let timestamp = |day: f64| ((day - UNIX_EPOCH) * 86400.0).round() as i64;
+    Some((
+        timestamp(transit - hour_angle),
+        timestamp(transit + hour_angle),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_days() {
+        let offset = FixedOffset::east_opt(11 * 3600).unwrap();
+        // 2024-06-21 14:00 UTC is 2024-06-22 01:00 in UTC+11
+        let t = 1_718_978_400;
+        assert_eq!(
+            day_of(t, offset),
+            day_of(t, FixedOffset::east_opt(0).unwrap()) + 1
+        );
+        assert_eq!(local_hour(t, offset), 1);
+    }
+
+    #[test]
+    fn sun_times_paris() {
+        // 2024-06-21 12:00 UTC: sunrise at 03:47 UTC and sunset at 19:58 UTC
+        let (sunrise, sunset) = sun_times(48.8566, 2.3522, 1_718_971_200).unwrap();
+        assert!((sunrise - 1_718_941_620).abs() < 120, "{}", sunrise);
+        assert!((sunset - 1_718_999_880).abs() < 120, "{}", sunset);
+    }
+
+    #[test]
+    fn sun_times_polar() {
+        // 2024-06-21 in Svalbard (polar day) and Antarctica (polar night)
+        assert!(sun_times(78.22, 15.65, 1_718_971_200).is_none());
+        assert!(sun_times(-77.85, 166.67, 1_718_971_200).is_none());
+    }
+}
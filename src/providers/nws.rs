@@ -0,0 +1,642 @@
+use crate::{
+    api::{current::*, one_call::*, Coord, Response, Weather},
+    providers::{self, open_meteo, Unsupported, WeatherProvider, UNKNOWN_CONDITION},
+    Location, QueryKind, StatusError, UnitMode, WeatherClient,
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use log::*;
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize};
+
+const API_URL: &str = "https://api.weather.gov";
+const HOST: &str = "api.weather.gov";
+const NAME: &str = "NWS";
+
+/// The US National Weather Service (https://www.weather.gov), which doesn't require an API key.
+///
+/// Only locations in the United States are covered. The forecasts don't include pressure,
+/// cloud cover nor precipitation amounts, and sunrise and sunset times are estimated from
+/// the coordinates.
+pub struct Nws;
+
+impl Nws {
+    pub fn new() -> Self {
+        Nws
+    }
+}
+
+impl Default for Nws {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for Nws {
+    fn name(&self) -> &'static str {
+        "nws"
+    }
+
+    async fn query(
+        &self,
+        client: &WeatherClient,
+        kind: QueryKind,
+        location: &Location,
+        language: Option<&str>,
+        units: UnitMode,
+    ) -> Result<Response> {
+        debug!("querying {:?} with {:?} NWS API", location, kind);
+
+        match kind {
+            QueryKind::Pollution => bail!(Unsupported::new(NAME, "air quality data")),
+            QueryKind::LocationName => bail!(Unsupported::new(NAME, "reverse geocoding")),
+            _ => {}
+        }
+
+        let mut place = match location {
+            Location::LatLon(lat, lon) => open_meteo::Place {
+                name: location.to_string(),
+                latitude: *lat,
+                longitude: *lon,
+                country_code: None,
+            },
+            Location::Place(p) => open_meteo::geocode(client, p, language).await?,
+            Location::Zip(..) | Location::CityId(_) => {
+                bail!(Unsupported::new(NAME, "postal codes and city IDs"))
+            }
+        };
+
+        // the API redirects requests with more than 4 decimals
+        let lat = format!("{:.4}", place.latitude);
+        let lon = format!("{:.4}", place.longitude);
+
+        let points: PointsResponse = get(
+            client,
+            &format!("{}/points/{},{}", API_URL, lat, lon),
+            &format!("nws-points-{}_{}", lat, lon),
+        )
+        .await
+        .map_err(|e| match e.downcast_ref::<StatusError>() {
+            // the API doesn't know points outside of the United States
+            Some(s) if s.status == StatusCode::NOT_FOUND => {
+                Unsupported::new(NAME, "locations outside of the United States").into()
+            }
+            _ => e.context(format!(
+                "no forecast from the National Weather Service for '{}'",
+                location
+            )),
+        })?;
+        let points = points.properties;
+
+        if let (Location::LatLon(..), Some(l)) = (location, &points.relative_location) {
+            place.name = format!("{}, {}", l.properties.city, l.properties.state);
+        }
+        place.country_code = Some("US".to_owned());
+
+        let grid = format!("{}_{}_{}", points.grid_id, points.grid_x, points.grid_y);
+        let hourly: ForecastResponse = get(
+            client,
+            &points.forecast_hourly,
+            &format!("nws-hourly-{}", grid),
+        )
+        .await?;
+        let hourly = parse_periods(hourly)?;
+
+        let conv = Converter {
+            units,
+            offset: hourly[0].1,
+        };
+
+        match kind {
+            QueryKind::Current => Ok(Response::from_current(conv.make_current(&hourly, place))),
+            _ => {
+                let daily: ForecastResponse =
+                    get(client, &points.forecast, &format!("nws-forecast-{}", grid)).await?;
+                let alerts = get::<AlertsResponse>(
+                    client,
+                    &format!("{}/alerts/active?point={},{}", API_URL, lat, lon),
+                    &format!("nws-alerts-{}_{}", lat, lon),
+                )
+                .await
+                .map(|a| a.features)
+                .unwrap_or_else(|e| {
+                    warn!("could not get the NWS alerts: {:#}", e);
+                    Vec::new()
+                });
+
+                Ok(Response::from_forecast(conv.make_forecast(
+                    &hourly,
+                    &parse_periods(daily)?,
+                    alerts,
+                    place,
+                )))
+            }
+        }
+    }
+}
+
+async fn get<T: DeserializeOwned>(client: &WeatherClient, url: &str, key: &str) -> Result<T> {
+    let request = client
+        .http()
+        .get(url)
+        .header(reqwest::header::ACCEPT, "application/geo+json");
+    let bytes = client.fetch_conditional(request, HOST, key).await?;
+
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+#[derive(Deserialize)]
+struct PointsResponse {
+    properties: PointProperties,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PointProperties {
+    grid_id: String,
+    grid_x: i32,
+    grid_y: i32,
+    forecast: String,
+    forecast_hourly: String,
+    relative_location: Option<RelativeLocation>,
+}
+
+#[derive(Deserialize)]
+struct RelativeLocation {
+    properties: RelativeLocationProperties,
+}
+
+#[derive(Deserialize)]
+struct RelativeLocationProperties {
+    city: String,
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct ForecastResponse {
+    properties: ForecastProperties,
+}
+
+#[derive(Deserialize)]
+struct ForecastProperties {
+    periods: Vec<Period>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Period {
+    start_time: String,
+    is_daytime: bool,
+    temperature: Option<f32>,
+    temperature_unit: Option<String>,
+    probability_of_precipitation: Option<QuantitativeValue>,
+    relative_humidity: Option<QuantitativeValue>,
+    wind_speed: Option<String>,
+    wind_direction: Option<String>,
+    icon: Option<String>,
+    short_forecast: String,
+}
+
+#[derive(Deserialize)]
+struct QuantitativeValue {
+    value: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct AlertsResponse {
+    features: Vec<AlertFeature>,
+}
+
+#[derive(Deserialize)]
+struct AlertFeature {
+    properties: AlertProperties,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AlertProperties {
+    event: String,
+    sender_name: Option<String>,
+    effective: Option<String>,
+    onset: Option<String>,
+    expires: Option<String>,
+    ends: Option<String>,
+    description: Option<String>,
+    severity: Option<String>,
+    urgency: Option<String>,
+}
+
+impl Period {
+    fn celsius(&self) -> Option<f32> {
+        match self.temperature_unit.as_deref() {
+            Some("F") => self.temperature.map(|t| (t - 32.0) / 1.8),
+            _ => self.temperature,
+        }
+    }
+
+    fn humidity(&self) -> Option<f32> {
+        self.relative_humidity.as_ref().and_then(|h| h.value)
+    }
+
+    fn pop(&self) -> Option<f32> {
+        self.probability_of_precipitation
+            .as_ref()
+            .and_then(|p| p.value)
+            .map(|p| p / 100.0)
+    }
+
+    /// Wind speed in m/s; ranges such as "5 to 10 mph" give the highest speed.
+    fn wind_speed(&self) -> Option<f32> {
+        let s = self.wind_speed.as_deref()?;
+        let speed = s
+            .split_whitespace()
+            .filter_map(|w| w.parse::<f32>().ok())
+            .reduce(f32::max)?;
+
+        if s.ends_with("mph") {
+            Some(speed * 0.447_04)
+        } else if s.ends_with("km/h") {
+            Some(speed / 3.6)
+        } else if s.ends_with("kt") {
+            Some(speed * 0.514_444)
+        } else {
+            Some(speed)
+        }
+    }
+
+    fn wind_deg(&self) -> Option<f32> {
+        const DIRECTIONS: [&str; 16] = [
+            "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW",
+            "NW", "NNW",
+        ];
+        let dir = self.wind_direction.as_deref()?;
+
+        DIRECTIONS
+            .iter()
+            .position(|d| *d == dir)
+            .map(|i| i as f32 * 22.5)
+    }
+
+    fn apparent_temperature(&self) -> Option<f32> {
+        self.celsius()
+            .map(|t| providers::apparent_temperature(t, self.humidity(), self.wind_speed()))
+    }
+}
+
+fn parse_periods(resp: ForecastResponse) -> Result<Vec<(i64, FixedOffset, Period)>> {
+    let periods: Vec<_> = resp
+        .properties
+        .periods
+        .into_iter()
+        .filter_map(|p| match DateTime::parse_from_rfc3339(&p.start_time) {
+            Ok(t) => Some((t.timestamp(), *t.offset(), p)),
+            Err(e) => {
+                debug!("invalid time '{}': {}", p.start_time, e);
+                None
+            }
+        })
+        .collect();
+
+    if periods.is_empty() {
+        bail!("empty forecast in NWS response");
+    }
+
+    Ok(periods)
+}
+
+fn parse_time(s: &Option<String>) -> Option<i64> {
+    s.as_deref()
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|t| t.timestamp())
+}
+
+struct Converter {
+    units: UnitMode,
+    offset: FixedOffset,
+}
+
+impl Converter {
+    fn temp(&self, celsius: f32) -> f32 {
+        providers::temperature(celsius, self.units)
+    }
+
+    fn speed(&self, meters_per_sec: f32) -> f32 {
+        providers::speed(meters_per_sec, self.units)
+    }
+
+    fn make_current(
+        &self,
+        hourly: &[(i64, FixedOffset, Period)],
+        place: open_meteo::Place,
+    ) -> CurrentResponse {
+        let (dt, _, p) = &hourly[0];
+        let today = self.day_of(*dt);
+        let temp = p.celsius().unwrap_or_default();
+        let (temp_min, temp_max) = hourly
+            .iter()
+            .filter(|(t, _, _)| self.day_of(*t) == today)
+            .filter_map(|(_, _, p)| p.celsius())
+            .fold((temp, temp), |(min, max), t| (min.min(t), max.max(t)));
+        let (sunrise, sunset) = sun_times(&place, *dt);
+
+        CurrentResponse {
+            coord: Coord {
+                lat: place.latitude,
+                lon: place.longitude,
+            },
+            weather: vec![make_weather(p)],
+            main: Main {
+                temp: self.temp(temp),
+                feels_like: self.temp(p.apparent_temperature().unwrap_or(temp)),
+                temp_min: self.temp(temp_min),
+                temp_max: self.temp(temp_max),
+                pressure: None,
+                humidity: p.humidity().map(|v| v.round() as u8),
+            },
+            visibility: None,
+            wind: p.wind_speed().map(|speed| Wind {
+                speed: self.speed(speed),
+                deg: p.wind_deg(),
+                gale: None,
+            }),
+            rain: None,
+            snow: None,
+            clouds: None,
+            dt: *dt,
+            sys: Sys {
+                country: place.country_code.unwrap_or_default(),
+                sunrise,
+                sunset,
+            },
+            timezone: self.offset.local_minus_utc(),
+            id: 0,
+            name: place.name,
+        }
+    }
+
+    fn make_forecast(
+        &self,
+        hourly: &[(i64, FixedOffset, Period)],
+        daily: &[(i64, FixedOffset, Period)],
+        alerts: Vec<AlertFeature>,
+        place: open_meteo::Place,
+    ) -> OneCallResponse {
+        let (sunrise, sunset) = sun_times(&place, hourly[0].0);
+        let mut current = self.make_hour(hourly[0].0, &hourly[0].2);
+        current.sunrise = sunrise;
+        current.sunset = sunset;
+
+        // daytime and overnight periods, grouped by local day
+        let mut days: Vec<Vec<&(i64, FixedOffset, Period)>> = Vec::new();
+        for period in daily {
+            match days.last_mut() {
+                Some(day) if self.day_of(day[0].0) == self.day_of(period.0) => day.push(period),
+                _ => days.push(vec![period]),
+            }
+        }
+
+        let alerts = alerts
+            .into_iter()
+            .filter_map(|f| make_alert(f.properties))
+            .collect();
+
+        OneCallResponse {
+            lat: place.latitude as f32,
+            lon: place.longitude as f32,
+            timezone_offset: self.offset.local_minus_utc(),
+            current,
+            minutely: None,
+            hourly: Some(
+                hourly
+                    .iter()
+                    .map(|(t, _, p)| self.make_hour(*t, p))
+                    .collect(),
+            ),
+            daily: Some(days.iter().map(|d| self.make_day(d, hourly)).collect()),
+            alerts: Some(alerts),
+            overview: None,
+        }
+    }
+
+    fn make_hour(&self, dt: i64, p: &Period) -> WeatherData {
+        let temp = p.celsius().unwrap_or_default();
+
+        WeatherData {
+            dt,
+            sunrise: None,
+            sunset: None,
+            temp: Temperature::Value(self.temp(temp)),
+            feels_like: FeelsLike::Value(self.temp(p.apparent_temperature().unwrap_or(temp))),
+            pressure: None,
+            humidity: p.humidity().map(|v| v.round() as u8),
+            clouds: None,
+            visibility: None,
+            wind_speed: self.speed(p.wind_speed().unwrap_or_default()),
+            wind_deg: p.wind_deg(),
+            wind_gust: None,
+            pop: p.pop(),
+            rain: None,
+            snow: None,
+            weather: vec![make_weather(p)],
+            summary: None,
+        }
+    }
+
+    fn make_day(
+        &self,
+        periods: &[&(i64, FixedOffset, Period)],
+        hourly: &[(i64, FixedOffset, Period)],
+    ) -> WeatherData {
+        let day = self.day_of(periods[0].0);
+        let daytime = periods.iter().find(|(_, _, p)| p.is_daytime);
+        let overnight = periods.iter().find(|(_, _, p)| !p.is_daytime);
+        let main = daytime.or(overnight).map(|p| &p.2).unwrap_or(&periods[0].2);
+
+        let hours: Vec<_> = hourly
+            .iter()
+            .filter(|(t, _, _)| self.day_of(*t) == day)
+            .collect();
+        // the overnight low is usually reached the next morning, so hourly temperatures
+        // are also needed to get the extremes of the day
+        let temps: Vec<f32> = daytime
+            .into_iter()
+            .chain(overnight)
+            .copied()
+            .chain(hours.iter().copied())
+            .filter_map(|(_, _, p)| p.celsius())
+            .collect();
+        let max = temps.iter().copied().reduce(f32::max).unwrap_or_default();
+        let min = temps.iter().copied().reduce(f32::min).unwrap_or_default();
+
+        // the hour closest to a given local hour of the day
+        let at_hour = |hour: i64| {
+            hours
+                .iter()
+                .min_by_key(|(t, _, _)| (self.local_hour(*t) - hour).abs())
+                .map(|h| &h.2)
+        };
+        let temp_at = |hour, fallback| at_hour(hour).and_then(|p| p.celsius()).unwrap_or(fallback);
+        let feels_at = |hour, fallback| {
+            at_hour(hour)
+                .and_then(|p| p.apparent_temperature())
+                .unwrap_or(fallback)
+        };
+
+        let pop = periods
+            .iter()
+            .filter_map(|(_, _, p)| p.pop())
+            .reduce(f32::max);
+
+        WeatherData {
+            dt: day * 86400 - self.offset.local_minus_utc() as i64 + 12 * 3600,
+            sunrise: None,
+            sunset: None,
+            temp: Temperature::Values(TempValues {
+                morn: self.temp(temp_at(9, min)),
+                day: self.temp(temp_at(15, max)),
+                eve: self.temp(temp_at(19, max)),
+                night: self.temp(temp_at(23, min)),
+                min: self.temp(min),
+                max: self.temp(max),
+            }),
+            feels_like: FeelsLike::Values(FeelsLikeValues {
+                morn: self.temp(feels_at(9, min)),
+                day: self.temp(feels_at(15, max)),
+                eve: self.temp(feels_at(19, max)),
+                night: self.temp(feels_at(23, min)),
+            }),
+            pressure: None,
+            humidity: main.humidity().map(|v| v.round() as u8),
+            clouds: None,
+            visibility: None,
+            wind_speed: self.speed(main.wind_speed().unwrap_or_default()),
+            wind_deg: main.wind_deg(),
+            wind_gust: None,
+            pop,
+            rain: None,
+            snow: None,
+            weather: vec![make_weather(main)],
+            summary: None,
+        }
+    }
+
+    fn day_of(&self, t: i64) -> i64 {
+        providers::day_of(t, self.offset)
+    }
+
+    fn local_hour(&self, t: i64) -> i64 {
+        providers::local_hour(t, self.offset)
+    }
+}
+
+/// Sunrise and sunset of the day of `dt`, estimated from the coordinates.
+fn sun_times(place: &open_meteo::Place, dt: i64) -> (Option<i64>, Option<i64>) {
+    match providers::sun_times(place.latitude, place.longitude, dt) {
+        Some((sunrise, sunset)) => (Some(sunrise), Some(sunset)),
+        None => (None, None),
+    }
+}
+
+/// Converts an NWS alert, or drops it if it doesn't say when it starts and ends.
+fn make_alert(a: AlertProperties) -> Option<Alert> {
+    let start = parse_time(&a.onset).or_else(|| parse_time(&a.effective));
+    let end = parse_time(&a.ends).or_else(|| parse_time(&a.expires));
+    let (start, end) = match (start, end) {
+        (Some(start), Some(end)) => (start, end),
+        _ => {
+            debug!("ignoring NWS alert '{}' without start or end time", a.event);
+            return None;
+        }
+    };
+
+    Some(Alert {
+        sender_name: a.sender_name.unwrap_or_else(|| "NWS".to_owned()),
+        tags: alert_tags(&a.event),
+        event: a.event,
+        start,
+        end,
+        description: a.description.unwrap_or_default(),
+        severity: a.severity.filter(|s| s != "Unknown"),
+        urgency: a.urgency.filter(|u| u != "Unknown"),
+    })
+}
+
+/// Finds the OpenWeather alert tags matching an NWS event (e.g. "Winter Storm Warning"),
+/// so that the alerts segment can show the same icons.
+fn alert_tags(event: &str) -> Vec<String> {
+    const TAGS: [(&[&str], &str); 8] = [
+        (&["Flood"], "Flood"),
+        (&["Wind", "Hurricane", "Tropical Storm"], "Wind"),
+        (&["Rain"], "Rain"),
+        (&["Thunderstorm", "Tornado"], "Thunderstorm"),
+        (&["Fog"], "Fog"),
+        (
+            &["Coastal", "Surf", "Rip Current", "Beach"],
+            "Coastal event",
+        ),
+        (
+            &["Heat", "Cold", "Chill", "Freeze", "Frost"],
+            "Extreme temperature value",
+        ),
+        (&["Winter", "Snow", "Ice", "Blizzard", "Sleet"], "Snow/Ice"),
+    ];
+
+    TAGS.iter()
+        .filter(|(words, _)| words.iter().any(|w| event.contains(w)))
+        .map(|(_, tag)| tag.to_string())
+        .collect()
+}
+
+fn make_weather(p: &Period) -> Weather {
+    let (id, main) = icon_to_openweather(p.icon.as_deref().unwrap_or_default());
+
+    Weather {
+        id,
+        main: main.to_owned(),
+        description: Some(p.short_forecast.clone()),
+        icon: String::new(),
+    }
+}
+
+/// Maps NWS icon URLs (e.g. "https://api.weather.gov/icons/land/day/tsra,40/ovc?size=medium")
+/// to OpenWeather conditions.
+fn icon_to_openweather(icon: &str) -> (u16, &'static str) {
+    // when the conditions change during the period, the first one is used
+    let condition = icon
+        .split('?')
+        .next()
+        .and_then(|path| path.split("/land/").nth(1))
+        .and_then(|path| path.split('/').nth(1))
+        .and_then(|c| c.split(',').next())
+        .unwrap_or_default();
+
+    match condition.strip_prefix("wind_").unwrap_or(condition) {
+        "skc" | "hot" | "cold" => (800, "Clear"),
+        "few" => (801, "Clouds"),
+        "sct" => (802, "Clouds"),
+        "bkn" => (803, "Clouds"),
+        "ovc" => (804, "Clouds"),
+        "snow" => (601, "Snow"),
+        "blizzard" => (602, "Snow"),
+        "rain_snow" => (616, "Snow"),
+        "rain_sleet" | "snow_sleet" | "sleet" => (613, "Snow"),
+        "fzra" | "rain_fzra" | "snow_fzra" => (511, "Rain"),
+        "rain" => (501, "Rain"),
+        "rain_showers" => (521, "Rain"),
+        "rain_showers_hi" => (520, "Rain"),
+        "tsra" | "tsra_sct" => (201, "Thunderstorm"),
+        "tsra_hi" => (200, "Thunderstorm"),
+        "tornado" => (781, "Tornado"),
+        "hurricane" | "tropical_storm" => (771, "Squall"),
+        "dust" => (731, "Dust"),
+        "smoke" => (711, "Smoke"),
+        "haze" => (721, "Haze"),
+        "fog" => (741, "Fog"),
+        c => {
+            debug!("unknown NWS weather icon: {}", c);
+            (UNKNOWN_CONDITION, "Unknown")
+        }
+    }
+}
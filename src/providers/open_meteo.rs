@@ -0,0 +1,596 @@
+use crate::{
+    api::{current::*, one_call::*, pollution::*, Coord, Rain, Response, Snow, Weather},
+    providers::{Unsupported, WeatherProvider, UNKNOWN_CONDITION},
+    Location, QueryKind, UnitMode, WeatherClient,
+};
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use log::*;
+use serde::Deserialize;
+
+const FORECAST_API_URL: &str = "https://api.open-meteo.com/v1/forecast";
+const AIR_QUALITY_API_URL: &str = "https://air-quality-api.open-meteo.com/v1/air-quality";
+const GEOCODING_API_URL: &str = "https://geocoding-api.open-meteo.com/v1/search";
+const HOST: &str = "open-meteo.com";
+const NAME: &str = "Open-Meteo";
+
+const CURRENT_FIELDS: &str = "temperature_2m,relative_humidity_2m,apparent_temperature,\
+    rain,snowfall,weather_code,cloud_cover,pressure_msl,wind_speed_10m,\
+    wind_direction_10m,wind_gusts_10m";
+const HOURLY_FIELDS: &str = "temperature_2m,relative_humidity_2m,apparent_temperature,\
+    precipitation_probability,rain,snowfall,weather_code,cloud_cover,pressure_msl,visibility,\
+    wind_speed_10m,wind_direction_10m,wind_gusts_10m";
+const DAILY_FIELDS: &str = "weather_code,temperature_2m_max,temperature_2m_min,sunrise,sunset,\
+    rain_sum,snowfall_sum,precipitation_probability_max,wind_speed_10m_max,wind_gusts_10m_max,\
+    wind_direction_10m_dominant";
+const POLLUTION_FIELDS: &str = "european_aqi,carbon_monoxide,nitrogen_dioxide,sulphur_dioxide,\
+    ozone,pm2_5,pm10,ammonia";
+
+/// Number of days to ask for: today, and the 7 days displayable by `daily_forecast`.
+const FORECAST_DAYS: &str = "8";
+
+/// Open-Meteo (https://open-meteo.com), which doesn't require an API key.
+pub struct OpenMeteo;
+
+impl OpenMeteo {
+    pub fn new() -> Self {
+        OpenMeteo
+    }
+}
+
+impl Default for OpenMeteo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for OpenMeteo {
+    fn name(&self) -> &'static str {
+        "open_meteo"
+    }
+
+    async fn query(
+        &self,
+        client: &WeatherClient,
+        kind: QueryKind,
+        location: &Location,
+        language: Option<&str>,
+        units: UnitMode,
+    ) -> Result<Response> {
+        debug!("querying {:?} with {:?} Open-Meteo API", location, kind);
+
+        if let QueryKind::LocationName = kind {
+            bail!(Unsupported::new(NAME, "reverse geocoding"));
+        }
+
+        let place = match location {
+            Location::LatLon(lat, lon) => Place {
+                name: location.to_string(),
+                latitude: *lat,
+                longitude: *lon,
+                country_code: None,
+            },
+            Location::Place(p) => geocode(client, p, language).await?,
+            Location::Zip(..) | Location::CityId(_) => {
+                bail!(Unsupported::new(NAME, "postal codes and city IDs"))
+            }
+        };
+
+        let mut params = vec![
+            ("latitude", place.latitude.to_string()),
+            ("longitude", place.longitude.to_string()),
+            ("timeformat", "unixtime".to_owned()),
+            ("timezone", "auto".to_owned()),
+        ];
+
+        if let QueryKind::Pollution = kind {
+            params.push(("current", POLLUTION_FIELDS.to_owned()));
+
+            let bytes = client
+                .fetch(client.http().get(AIR_QUALITY_API_URL).query(&params), HOST)
+                .await?;
+            let resp: PollutionApiResponse = parse(&bytes, location)?;
+
+            return Ok(Response::from_pollution(make_pollution(resp)));
+        }
+
+        let (temperature_unit, wind_speed_unit) = match units {
+            UnitMode::Imperial => ("fahrenheit", "mph"),
+            _ => ("celsius", "ms"),
+        };
+        params.push(("temperature_unit", temperature_unit.to_owned()));
+        params.push(("wind_speed_unit", wind_speed_unit.to_owned()));
+        params.push(("current", CURRENT_FIELDS.to_owned()));
+
+        match kind {
+            QueryKind::Current => {
+                params.push((
+                    "daily",
+                    "temperature_2m_max,temperature_2m_min,sunrise,sunset".to_owned(),
+                ));
+                params.push(("forecast_days", "1".to_owned()));
+            }
+            _ => {
+                params.push(("hourly", HOURLY_FIELDS.to_owned()));
+                params.push(("daily", DAILY_FIELDS.to_owned()));
+                params.push(("forecast_days", FORECAST_DAYS.to_owned()));
+            }
+        }
+
+        let bytes = client
+            .fetch(client.http().get(FORECAST_API_URL).query(&params), HOST)
+            .await?;
+        let resp: ForecastApiResponse = parse(&bytes, location)?;
+
+        let conv = Converter { units };
+        match kind {
+            QueryKind::Current => Ok(Response::from_current(conv.make_current(resp, place)?)),
+            _ => Ok(Response::from_forecast(conv.make_forecast(resp)?)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ApiResponse<T> {
+    Error { reason: String },
+    Success(T),
+}
+
+fn parse<'a, T: Deserialize<'a>>(bytes: &'a [u8], location: &Location) -> Result<T> {
+    match serde_json::from_slice(bytes)? {
+        ApiResponse::Success(t) => Ok(t),
+        ApiResponse::Error { reason } => {
+            bail!("error from Open-Meteo API for '{}': {}", location, reason)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct GeocodingResponse {
+    #[serde(default)]
+    results: Vec<Place>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Place {
+    pub(crate) name: String,
+    pub(crate) latitude: f64,
+    pub(crate) longitude: f64,
+    pub(crate) country_code: Option<String>,
+}
+
+/// Resolves a place name, optionally followed by a country ("Paris, FR"), into coordinates.
+pub(crate) async fn geocode(
+    client: &WeatherClient,
+    place: &str,
+    language: Option<&str>,
+) -> Result<Place> {
+    let (name, country) = match place.split_once(',') {
+        Some((name, country)) => (name.trim(), Some(country.trim())),
+        None => (place.trim(), None),
+    };
+
+    let mut params = vec![("name", name.to_owned()), ("count", "10".to_owned())];
+    if let Some(language) = language {
+        let l_code = language.split_once('_').map(|t| t.0).unwrap_or(language);
+        params.push(("language", l_code.to_owned()));
+    }
+
+    let bytes = client
+        .fetch(client.http().get(GEOCODING_API_URL).query(&params), HOST)
+        .await?;
+    let resp: GeocodingResponse = serde_json::from_slice(&bytes)?;
+
+    resp.results
+        .into_iter()
+        .find(|p| match (country, &p.country_code) {
+            (Some(c), Some(code)) => c.eq_ignore_ascii_case(code),
+            (Some(_), None) => false,
+            (None, _) => true,
+        })
+        .ok_or_else(|| anyhow!("location error: no place found for '{}'", place))
+}
+
+#[derive(Deserialize)]
+struct TimeZoneResponse {
+    utc_offset_seconds: i32,
+}
+
+/// Finds the current offset from UTC (in seconds) of the time zone at the given coordinates,
+/// for providers that don't give it.
+pub(crate) async fn utc_offset(client: &WeatherClient, lat: &str, lon: &str) -> Result<i32> {
+    // without any weather variable, only the location and its time zone are returned
+    let request = client.http().get(FORECAST_API_URL).query(&[
+        ("latitude", lat),
+        ("longitude", lon),
+        ("timezone", "auto"),
+        ("forecast_days", "1"),
+    ]);
+    let bytes = client
+        .fetch_conditional(
+            request,
+            HOST,
+            &format!("open_meteo-timezone-{}_{}", lat, lon),
+        )
+        .await?;
+    let resp: TimeZoneResponse = serde_json::from_slice(&bytes)?;
+
+    Ok(resp.utc_offset_seconds)
+}
+
+#[derive(Deserialize)]
+struct ForecastApiResponse {
+    latitude: f64,
+    longitude: f64,
+    utc_offset_seconds: i32,
+    current: CurrentData,
+    hourly: Option<HourlyData>,
+    daily: Option<DailyData>,
+}
+
+#[derive(Deserialize)]
+struct CurrentData {
+    time: i64,
+    temperature_2m: Option<f32>,
+    relative_humidity_2m: Option<f32>,
+    apparent_temperature: Option<f32>,
+    rain: Option<f32>,
+    snowfall: Option<f32>,
+    weather_code: Option<u8>,
+    cloud_cover: Option<f32>,
+    pressure_msl: Option<f32>,
+    wind_speed_10m: Option<f32>,
+    wind_direction_10m: Option<f32>,
+    wind_gusts_10m: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct HourlyData {
+    time: Vec<i64>,
+    temperature_2m: Vec<Option<f32>>,
+    relative_humidity_2m: Vec<Option<f32>>,
+    apparent_temperature: Vec<Option<f32>>,
+    precipitation_probability: Vec<Option<f32>>,
+    rain: Vec<Option<f32>>,
+    snowfall: Vec<Option<f32>>,
+    weather_code: Vec<Option<u8>>,
+    cloud_cover: Vec<Option<f32>>,
+    pressure_msl: Vec<Option<f32>>,
+    visibility: Vec<Option<f32>>,
+    wind_speed_10m: Vec<Option<f32>>,
+    wind_direction_10m: Vec<Option<f32>>,
+    wind_gusts_10m: Vec<Option<f32>>,
+}
+
+#[derive(Deserialize)]
+struct DailyData {
+    time: Vec<i64>,
+    temperature_2m_max: Vec<Option<f32>>,
+    temperature_2m_min: Vec<Option<f32>>,
+    sunrise: Vec<Option<i64>>,
+    sunset: Vec<Option<i64>>,
+    #[serde(default)]
+    weather_code: Vec<Option<u8>>,
+    #[serde(default)]
+    rain_sum: Vec<Option<f32>>,
+    #[serde(default)]
+    snowfall_sum: Vec<Option<f32>>,
+    #[serde(default)]
+    precipitation_probability_max: Vec<Option<f32>>,
+    #[serde(default)]
+    wind_speed_10m_max: Vec<Option<f32>>,
+    #[serde(default)]
+    wind_gusts_10m_max: Vec<Option<f32>>,
+    #[serde(default)]
+    wind_direction_10m_dominant: Vec<Option<f32>>,
+}
+
+#[derive(Deserialize)]
+struct PollutionApiResponse {
+    latitude: f64,
+    longitude: f64,
+    current: PollutionCurrent,
+}
+
+#[derive(Deserialize)]
+struct PollutionCurrent {
+    time: i64,
+    european_aqi: Option<f32>,
+    carbon_monoxide: Option<f64>,
+    nitrogen_dioxide: Option<f64>,
+    sulphur_dioxide: Option<f64>,
+    ozone: Option<f64>,
+    pm2_5: Option<f64>,
+    pm10: Option<f64>,
+    ammonia: Option<f64>,
+}
+
+fn get<T: Copy>(values: &[Option<T>], i: usize) -> Option<T> {
+    values.get(i).copied().flatten()
+}
+
+struct Converter {
+    units: UnitMode,
+}
+
+impl Converter {
+    fn temp(&self, t: f32) -> f32 {
+        match self.units {
+            UnitMode::Standard => t + 273.15,
+            _ => t,
+        }
+    }
+
+    fn make_current(&self, resp: ForecastApiResponse, place: Place) -> Result<CurrentResponse> {
+        let c = &resp.current;
+        let daily = resp
+            .daily
+            .as_ref()
+            .ok_or_else(|| anyhow!("missing daily data in Open-Meteo response"))?;
+        let temp = c
+            .temperature_2m
+            .ok_or_else(|| anyhow!("missing temperature in Open-Meteo response"))?;
+
+        Ok(CurrentResponse {
+            coord: Coord {
+                lat: resp.latitude,
+                lon: resp.longitude,
+            },
+            weather: vec![make_weather(c.weather_code)],
+            main: Main {
+                temp: self.temp(temp),
+                feels_like: self.temp(c.apparent_temperature.unwrap_or(temp)),
+                temp_min: self.temp(get(&daily.temperature_2m_min, 0).unwrap_or(temp)),
+                temp_max: self.temp(get(&daily.temperature_2m_max, 0).unwrap_or(temp)),
+                pressure: c.pressure_msl.map(|v| v.round() as u16),
+                humidity: c.relative_humidity_2m.map(|v| v.round() as u8),
+            },
+            visibility: None,
+            wind: c.wind_speed_10m.map(|speed| Wind {
+                speed,
+                deg: c.wind_direction_10m,
+                gale: c.wind_gusts_10m,
+            }),
+            rain: c.rain.filter(|r| *r > 0.0).map(|r| Rain {
+                one_h: Some(r),
+                three_h: None,
+            }),
+            // snowfall is given in centimeters
+            snow: c.snowfall.filter(|s| *s > 0.0).map(|s| Snow {
+                one_h: Some(s * 10.0),
+                three_h: None,
+            }),
+            clouds: c.cloud_cover.map(|all| Clouds {
+                all: all.round() as u16,
+            }),
+            dt: c.time,
+            sys: Sys {
+                country: place.country_code.unwrap_or_default(),
+                sunrise: get(&daily.sunrise, 0),
+                sunset: get(&daily.sunset, 0),
+            },
+            timezone: resp.utc_offset_seconds,
+            id: 0,
+            name: place.name,
+        })
+    }
+
+    fn make_forecast(&self, resp: ForecastApiResponse) -> Result<OneCallResponse> {
+        let c = &resp.current;
+        let hourly = resp
+            .hourly
+            .as_ref()
+            .ok_or_else(|| anyhow!("missing hourly data in Open-Meteo response"))?;
+        let daily = resp
+            .daily
+            .as_ref()
+            .ok_or_else(|| anyhow!("missing daily data in Open-Meteo response"))?;
+        let temp = c
+            .temperature_2m
+            .ok_or_else(|| anyhow!("missing temperature in Open-Meteo response"))?;
+
+        let current = WeatherData {
+            dt: c.time,
+            sunrise: get(&daily.sunrise, 0),
+            sunset: get(&daily.sunset, 0),
+            temp: Temperature::Value(self.temp(temp)),
+            feels_like: FeelsLike::Value(self.temp(c.apparent_temperature.unwrap_or(temp))),
+            pressure: c.pressure_msl.map(|v| v.round() as u16),
+            humidity: c.relative_humidity_2m.map(|v| v.round() as u8),
+            clouds: c.cloud_cover.map(|v| v.round() as u16),
+            visibility: None,
+            wind_speed: c.wind_speed_10m.unwrap_or_default(),
+            wind_deg: c.wind_direction_10m,
+            wind_gust: c.wind_gusts_10m,
+            pop: None,
+            rain: c.rain.map(RainResult::Value),
+            snow: c.snowfall.map(|s| SnowResult::Value(s * 10.0)),
+            weather: vec![make_weather(c.weather_code)],
+            summary: None,
+        };
+
+        // the first hourly entry is expected to be the current hour,
+        // but Open-Meteo starts at midnight; hours without a temperature are skipped
+        let hourly_data = (0..hourly.time.len())
+            .filter(|i| hourly.time[*i] + 3600 > c.time)
+            .filter_map(|i| Some((i, get(&hourly.temperature_2m, i)?)))
+            .map(|(i, temp)| WeatherData {
+                dt: hourly.time[i],
+                sunrise: None,
+                sunset: None,
+                temp: Temperature::Value(self.temp(temp)),
+                feels_like: FeelsLike::Value(
+                    self.temp(get(&hourly.apparent_temperature, i).unwrap_or(temp)),
+                ),
+                pressure: get(&hourly.pressure_msl, i).map(|v| v.round() as u16),
+                humidity: get(&hourly.relative_humidity_2m, i).map(|v| v.round() as u8),
+                clouds: get(&hourly.cloud_cover, i).map(|v| v.round() as u16),
+                visibility: get(&hourly.visibility, i).map(|v| v.min(u16::MAX as f32) as u16),
+                wind_speed: get(&hourly.wind_speed_10m, i).unwrap_or_default(),
+                wind_deg: get(&hourly.wind_direction_10m, i),
+                wind_gust: get(&hourly.wind_gusts_10m, i),
+                pop: get(&hourly.precipitation_probability, i).map(|p| p / 100.0),
+                rain: get(&hourly.rain, i).map(RainResult::Value),
+                snow: get(&hourly.snowfall, i).map(|s| SnowResult::Value(s * 10.0)),
+                weather: vec![make_weather(get(&hourly.weather_code, i))],
+                summary: None,
+            })
+            .collect();
+
+        // Open-Meteo has no morning/day/evening/night temperatures:
+        // pick them from the hourly forecast, falling back to the daily min/max;
+        // days without a min/max are skipped
+        let at_hour =
+            |values: &[Option<f32>], day: usize, hour: usize| get(values, day * 24 + hour);
+        let daily_data = (0..daily.time.len())
+            .filter_map(|d| {
+                let min = get(&daily.temperature_2m_min, d)?;
+                let max = get(&daily.temperature_2m_max, d)?;
+                let t = &hourly.temperature_2m;
+                let a = &hourly.apparent_temperature;
+
+                Some(WeatherData {
+                    dt: daily.time[d] + 12 * 3600,
+                    sunrise: get(&daily.sunrise, d),
+                    sunset: get(&daily.sunset, d),
+                    temp: Temperature::Values(TempValues {
+                        morn: self.temp(at_hour(t, d, 9).unwrap_or(min)),
+                        day: self.temp(at_hour(t, d, 15).unwrap_or(max)),
+                        eve: self.temp(at_hour(t, d, 19).unwrap_or(max)),
+                        night: self.temp(at_hour(t, d, 23).unwrap_or(min)),
+                        min: self.temp(min),
+                        max: self.temp(max),
+                    }),
+                    feels_like: FeelsLike::Values(FeelsLikeValues {
+                        morn: self.temp(at_hour(a, d, 9).unwrap_or(min)),
+                        day: self.temp(at_hour(a, d, 15).unwrap_or(max)),
+                        eve: self.temp(at_hour(a, d, 19).unwrap_or(max)),
+                        night: self.temp(at_hour(a, d, 23).unwrap_or(min)),
+                    }),
+                    pressure: at_hour(&hourly.pressure_msl, d, 12).map(|v| v.round() as u16),
+                    humidity: at_hour(&hourly.relative_humidity_2m, d, 12).map(|v| v.round() as u8),
+                    clouds: at_hour(&hourly.cloud_cover, d, 12).map(|v| v.round() as u16),
+                    visibility: None,
+                    wind_speed: get(&daily.wind_speed_10m_max, d).unwrap_or_default(),
+                    wind_deg: get(&daily.wind_direction_10m_dominant, d),
+                    wind_gust: get(&daily.wind_gusts_10m_max, d),
+                    pop: get(&daily.precipitation_probability_max, d).map(|p| p / 100.0),
+                    rain: get(&daily.rain_sum, d).map(RainResult::Value),
+                    snow: get(&daily.snowfall_sum, d).map(|s| SnowResult::Value(s * 10.0)),
+                    weather: vec![make_weather(get(&daily.weather_code, d))],
+                    summary: None,
+                })
+            })
+            .collect();
+
+        Ok(OneCallResponse {
+            lat: resp.latitude as f32,
+            lon: resp.longitude as f32,
+            timezone_offset: resp.utc_offset_seconds,
+            current,
+            minutely: None,
+            hourly: Some(hourly_data),
+            daily: Some(daily_data),
+            alerts: None,
+            overview: None,
+        })
+    }
+}
+
+fn make_pollution(resp: PollutionApiResponse) -> PollutionResponse {
+    let c = resp.current;
+    let coord = Coord {
+        lat: resp.latitude,
+        lon: resp.longitude,
+    };
+
+    // the European AQI goes from 0 to 100+ in steps of 20, with the same
+    // categories as the OpenWeather index: good, fair, moderate, poor, very poor...
+    let aqi = match c.european_aqi {
+        Some(a) if a <= 20.0 => 1,
+        Some(a) if a <= 40.0 => 2,
+        Some(a) if a <= 60.0 => 3,
+        Some(a) if a <= 80.0 => 4,
+        Some(_) => 5,
+        None => {
+            debug!("no air quality index in Open-Meteo's response");
+            return PollutionResponse {
+                coord,
+                list: Vec::new(),
+            };
+        }
+    };
+
+    PollutionResponse {
+        coord,
+        list: vec![PollutionData {
+            dt: c.time,
+            main: AQIndex { aqi },
+            components: Components {
+                co: c.carbon_monoxide,
+                // not provided by Open-Meteo
+                no: None,
+                no2: c.nitrogen_dioxide,
+                o3: c.ozone,
+                so2: c.sulphur_dioxide,
+                pm2_5: c.pm2_5,
+                pm10: c.pm10,
+                nh3: c.ammonia,
+            },
+        }],
+    }
+}
+
+pub(crate) fn make_weather(code: Option<u8>) -> Weather {
+    let (id, main, description) = match code {
+        Some(code) => wmo_to_openweather(code),
+        None => (UNKNOWN_CONDITION, "Unknown", "unknown"),
+    };
+
+    Weather {
+        id,
+        main: main.to_owned(),
+        description: Some(description.to_owned()),
+        icon: String::new(),
+    }
+}
+
+/// Maps WMO weather interpretation codes to OpenWeather conditions.
+fn wmo_to_openweather(code: u8) -> (u16, &'static str, &'static str) {
+    match code {
+        0 => (800, "Clear", "clear sky"),
+        1 => (801, "Clouds", "mainly clear"),
+        2 => (802, "Clouds", "partly cloudy"),
+        3 => (804, "Clouds", "overcast"),
+        45 => (741, "Fog", "fog"),
+        48 => (741, "Fog", "depositing rime fog"),
+        51 => (300, "Drizzle", "light drizzle"),
+        53 => (301, "Drizzle", "drizzle"),
+        55 => (302, "Drizzle", "dense drizzle"),
+        56 => (511, "Drizzle", "light freezing drizzle"),
+        57 => (511, "Drizzle", "dense freezing drizzle"),
+        61 => (500, "Rain", "light rain"),
+        63 => (501, "Rain", "moderate rain"),
+        65 => (502, "Rain", "heavy rain"),
+        66 => (511, "Rain", "light freezing rain"),
+        67 => (511, "Rain", "heavy freezing rain"),
+        71 => (600, "Snow", "light snow"),
+        73 => (601, "Snow", "snow"),
+        75 => (602, "Snow", "heavy snow"),
+        77 => (600, "Snow", "snow grains"),
+        80 => (520, "Rain", "light rain showers"),
+        81 => (521, "Rain", "rain showers"),
+        82 => (522, "Rain", "violent rain showers"),
+        85 => (620, "Snow", "light snow showers"),
+        86 => (622, "Snow", "heavy snow showers"),
+        95 => (211, "Thunderstorm", "thunderstorm"),
+        96 => (201, "Thunderstorm", "thunderstorm with light hail"),
+        99 => (202, "Thunderstorm", "thunderstorm with heavy hail"),
+        c => {
+            debug!("unknown WMO weather code: {}", c);
+            (UNKNOWN_CONDITION, "Unknown", "unknown")
+        }
+    }
+}
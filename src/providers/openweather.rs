@@ -0,0 +1,277 @@
+use crate::{
+    api::{
+        current::ApiResponse as CResponse,
+        geocoding::{ApiResponse as GResponse, Place},
+        one_call::{ApiResponse as OResponse, OverviewResponse},
+        pollution::ApiResponse as PResponse,
+        Response,
+    },
+    providers::WeatherProvider,
+    Location, QueryKind, StatusError, UnitMode, WeatherClient,
+};
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use log::*;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+const CURRENT_API_URL: &str = "https://api.openweathermap.org/data/2.5/weather";
+const ONECALL_2_5_API_URL: &str = "https://api.openweathermap.org/data/2.5/onecall";
+const ONECALL_3_0_API_URL: &str = "https://api.openweathermap.org/data/3.0/onecall";
+const OVERVIEW_API_URL: &str = "https://api.openweathermap.org/data/3.0/onecall/overview";
+const POLLUTION_API_URL: &str = "http://api.openweathermap.org/data/2.5/air_pollution";
+const GEOCODING_API_URL: &str = "https://api.openweathermap.org/geo/1.0/direct";
+const REVERSE_GEOCODING_API_URL: &str = "https://api.openweathermap.org/geo/1.0/reverse";
+const HOST: &str = "openweathermap.org";
+const TOO_MANY_CALLS: &str = "Too many calls to the API! If you not using your own API key, please get your own for free over at http://openweathermap.org";
+
+/// Version of the One Call API used for forecasts.
+///
+/// Newer keys only have access to the 3.0 version, which requires a separate subscription
+/// but also gives daily summaries and an overview of the day.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum OneCallVersion {
+    #[serde(rename = "2.5")]
+    V2_5,
+    #[serde(rename = "3.0")]
+    V3_0,
+}
+
+impl Default for OneCallVersion {
+    fn default() -> Self {
+        OneCallVersion::V2_5
+    }
+}
+
+pub struct OpenWeather {
+    key: String,
+    onecall_version: OneCallVersion,
+}
+
+impl OpenWeather {
+    pub fn new(key: String, onecall_version: OneCallVersion) -> Self {
+        OpenWeather {
+            key,
+            onecall_version,
+        }
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for OpenWeather {
+    fn name(&self) -> &'static str {
+        "openweather"
+    }
+
+    fn cache_variant(&self, kind: QueryKind) -> Option<&'static str> {
+        // the responses of both versions don't have the same data; the 2.5 version keeps
+        // the paths used before 3.0 was supported
+        match (kind, self.onecall_version) {
+            (QueryKind::ForeCast, OneCallVersion::V3_0) => Some("v3.0"),
+            _ => None,
+        }
+    }
+
+    async fn query(
+        &self,
+        client: &WeatherClient,
+        kind: QueryKind,
+        location: &Location,
+        language: Option<&str>,
+        units: UnitMode,
+    ) -> Result<Response> {
+        debug!("querying {:?} with {:?} OpenWeather API", location, kind);
+        if kind == QueryKind::LocationName && !matches!(location, Location::LatLon(..)) {
+            bail!("reverse geocoding needs coordinates, not '{}'", location);
+        }
+
+        let mut params = Vec::with_capacity(3);
+        match location {
+            Location::LatLon(lat, lon) => {
+                params.push(("lat", lat.to_string()));
+                params.push(("lon", lon.to_string()));
+            }
+            Location::Place(place) => params.push(("q", place.to_string())),
+            Location::Zip(code, country) => params.push(("zip", format!("{},{}", code, country))),
+            Location::CityId(id) => params.push(("id", id.to_string())),
+        };
+
+        // Adapt between locales and Openweather language codes:
+        // the codes OW accepts are a mix of ISO 639-1 language codes,
+        // ISO 3166 country codes and locale-like codes...
+        if let Some(language) = language {
+            params.push((
+                "lang",
+                make_openweather_language_codes(language).into_owned(),
+            ));
+        }
+
+        params.push(("appid", self.key.clone()));
+
+        params.push(("units", units.to_string()));
+
+        let api_url = match kind {
+            QueryKind::Current => CURRENT_API_URL,
+            QueryKind::ForeCast => match self.onecall_version {
+                OneCallVersion::V2_5 => ONECALL_2_5_API_URL,
+                OneCallVersion::V3_0 => ONECALL_3_0_API_URL,
+            },
+            QueryKind::Pollution => POLLUTION_API_URL,
+            QueryKind::LocationName => REVERSE_GEOCODING_API_URL,
+        };
+
+        let bytes = client
+            .fetch(client.http().get(api_url).query(&params), HOST)
+            .await
+            .map_err(|e| match e.downcast_ref::<StatusError>() {
+                Some(s) if s.status == StatusCode::TOO_MANY_REQUESTS => e.context(TOO_MANY_CALLS),
+                _ => e,
+            })?;
+
+        match kind {
+            QueryKind::Current => match serde_json::from_slice(&bytes)? {
+                CResponse::Success(w) => Ok(Response::from_current(w)),
+                CResponse::OtherInt { cod, message } => {
+                    handle_error(StatusCode::from_u16(cod)?, &message, location)
+                }
+                CResponse::OtherString { cod, message } => {
+                    handle_error(cod.parse()?, &message, location)
+                }
+            },
+            QueryKind::ForeCast => match serde_json::from_slice(&bytes)? {
+                OResponse::Success(mut w) => {
+                    if let (OneCallVersion::V3_0, Location::LatLon(..)) =
+                        (self.onecall_version, location)
+                    {
+                        w.overview = self.query_overview(client, &params).await;
+                    }
+                    Ok(Response::from_forecast(*w))
+                }
+                OResponse::OtherInt { cod, message } => {
+                    handle_error(StatusCode::from_u16(cod)?, &message, location)
+                }
+                OResponse::OtherString { cod, message } => {
+                    handle_error(cod.parse()?, &message, location)
+                }
+            },
+            QueryKind::Pollution => match serde_json::from_slice(&bytes)? {
+                PResponse::Success(p) => Ok(Response::from_pollution(p)),
+                PResponse::OtherInt { cod, message } => {
+                    handle_error(StatusCode::from_u16(cod)?, &message, location)
+                }
+                PResponse::OtherString { cod, message } => {
+                    handle_error(cod.parse()?, &message, location)
+                }
+            },
+            QueryKind::LocationName => match serde_json::from_slice(&bytes)? {
+                GResponse::Success(places) => {
+                    let place = places
+                        .first()
+                        .ok_or_else(|| anyhow!("no place found at {}", location))?;
+                    Ok(Response::from_location_name(format!(
+                        "{}, {}",
+                        place.local_name(language),
+                        place.country
+                    )))
+                }
+                GResponse::OtherInt { cod, message } => {
+                    handle_error(StatusCode::from_u16(cod)?, &message, location)
+                }
+                GResponse::OtherString { cod, message } => {
+                    handle_error(cod.parse()?, &message, location)
+                }
+            },
+        }
+    }
+}
+
+impl OpenWeather {
+    /// Gets the human-readable overview of today's weather, which is only available
+    /// from the One Call 3.0 API.
+    async fn query_overview(
+        &self,
+        client: &WeatherClient,
+        params: &[(&str, String)],
+    ) -> Option<String> {
+        // the overview endpoint doesn't support languages
+        let params: Vec<_> = params.iter().filter(|(k, _)| *k != "lang").collect();
+        let bytes = client
+            .fetch(client.http().get(OVERVIEW_API_URL).query(&params), HOST)
+            .await;
+
+        match bytes.map(|b| serde_json::from_slice::<OverviewResponse>(&b)) {
+            Ok(Ok(o)) => Some(o.weather_overview),
+            Ok(Err(e)) => {
+                warn!("failed to parse the weather overview: {}", e);
+                None
+            }
+            Err(e) => {
+                warn!("failed to get the weather overview: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Searches for places matching `query` (e.g. "Springfield, US") with OpenWeather's
+/// geocoding API, returning at most `limit` of them.
+pub async fn geocode(
+    client: &WeatherClient,
+    key: &str,
+    query: &str,
+    limit: u8,
+) -> Result<Vec<Place>> {
+    debug!("looking for '{}' with OpenWeather geocoding API", query);
+    let params = [
+        ("q", query.to_owned()),
+        ("limit", limit.to_string()),
+        ("appid", key.to_owned()),
+    ];
+
+    let bytes = client
+        .fetch(client.http().get(GEOCODING_API_URL).query(&params), HOST)
+        .await
+        .map_err(|e| match e.downcast_ref::<StatusError>() {
+            Some(s) if s.status == StatusCode::TOO_MANY_REQUESTS => e.context(TOO_MANY_CALLS),
+            _ => e,
+        })?;
+
+    match serde_json::from_slice(&bytes)? {
+        GResponse::Success(places) => Ok(places),
+        GResponse::OtherInt { cod, message } => {
+            bail!("error from OpenWeather API: {}: {}", cod, message)
+        }
+        GResponse::OtherString { cod, message } => {
+            bail!("error from OpenWeather API: {}: {}", cod, message)
+        }
+    }
+}
+
+fn handle_error(error_code: StatusCode, message: &str, location: &Location) -> Result<Response> {
+    match error_code {
+        StatusCode::NOT_FOUND => bail!("location error: '{}' for '{}'", message, location),
+        StatusCode::TOO_MANY_REQUESTS => bail!(TOO_MANY_CALLS),
+        _ => bail!("error from OpenWeather API: {}: {}", error_code, message),
+    }
+}
+
+fn make_openweather_language_codes(s: &str) -> Cow<'_, str> {
+    // openweather supports these directly
+    if let "zh_CN" | "zh_TW" | "pt_BR" = s {
+        return s.to_lowercase().into();
+    };
+
+    let l_code = s.split_once('_').map(|t| t.0).unwrap_or(s);
+
+    // openweather uses country codes for those
+    match l_code {
+        "sq" => "al",        // Albanian
+        "cs" => "cz",        // Czech
+        "ko" => "kr",        // Korean
+        "lv" => "la",        // Latvian
+        "nb" | "nn" => "no", // Norwegian
+        s => s,
+    }
+    .into()
+}
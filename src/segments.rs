@@ -1,14 +1,21 @@
-use crate::api::Response;
+use crate::api::{one_call::OneCallResponse, Response};
 use crate::{api::current::Wind, DisplayMode, WindType};
-use crate::{config::*, serde_utils::*, QueryKind, UnitMode};
+use crate::{
+    config::*,
+    markup::{hex, PromptWriter, Shell, StyledText},
+    providers::UNKNOWN_CONDITION,
+    serde_utils::*,
+    OutputFormat, QueryKind, UnitMode,
+};
 use anyhow::*;
-use chrono::{Datelike, FixedOffset, Locale, TimeZone, Utc};
+use chrono::{DateTime, Datelike, FixedOffset, Locale, TimeZone, Utc};
 use log::*;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::collections::HashSet;
 use std::convert::TryInto;
 use std::io::Write;
-use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
+use termcolor::{Buffer, Color, ColorSpec, WriteColor};
 
 macro_rules! display_print {
     ($out:ident, $display:expr, $a:expr, $b:expr, $c:expr) => {
@@ -47,9 +54,9 @@ impl Renderer {
         Renderer { display_config }
     }
 
-    pub fn render(
+    pub fn render<W: WriteColor>(
         &mut self,
-        out: &mut StandardStream,
+        out: &mut W,
         resp: &Response,
         language: Option<&str>,
     ) -> Result<()> {
@@ -58,8 +65,310 @@ impl Renderer {
             return Ok(());
         }
 
-        out.set_color(&self.display_config.base_style)?;
+        let conf = self.render_conf(language);
+        self.render_line(out, &conf, &self.display_config.segments, resp)?;
 
+        Ok(())
+    }
+
+    /// Writes the segments as the JSON object expected by Waybar's custom modules.
+    ///
+    /// The text is the usual line as Pango markup, and the tooltip shows the `tooltip`
+    /// segments, one per line.
+    pub fn render_waybar(
+        &mut self,
+        out: &mut dyn Write,
+        resp: &Response,
+        language: Option<&str>,
+    ) -> Result<()> {
+        let conf = self.render_conf(language);
+
+        let mut text = StyledText::new();
+        self.render_line(&mut text, &conf, &self.display_config.segments, resp)?;
+
+        let mut tooltip = Vec::new();
+        for s in &self.display_config.tooltip {
+            let mut line = StyledText::new();
+            // the data of the tooltip may be missing, if it couldn't be queried
+            if let Err(e) = self.render_line(&mut line, &conf, std::slice::from_ref(s), resp) {
+                debug!("leaving a segment out of the tooltip: {:#}", e);
+                continue;
+            }
+            if !line.is_empty() {
+                tooltip.push(line.to_pango());
+            }
+        }
+
+        let current = resp.as_current()?;
+        let mut class = vec![condition_class(current.weather[0].id)];
+        if is_night(current.dt, current.sys.sunrise, current.sys.sunset) {
+            class.push("night");
+        }
+        if resp
+            .as_forecast()
+            .ok()
+            .and_then(|f| f.alerts.as_ref())
+            .map_or(false, |a| !a.is_empty())
+        {
+            class.push("alert");
+        }
+
+        let doc = json!({
+            "text": text.to_pango(),
+            "tooltip": tooltip.join("\n"),
+            "class": class,
+            "percentage": current.clouds.as_ref().map_or(0, |c| c.all),
+        });
+        // Waybar reads one JSON object per line
+        serde_json::to_writer(&mut *out, &doc)?;
+        writeln!(out)?;
+
+        Ok(())
+    }
+
+    /// Writes the segments as one status line of the i3bar protocol, each segment being
+    /// its own block.
+    ///
+    /// The short text of a block is the segment in ASCII mode, without the icons.
+    pub fn render_i3bar(
+        &mut self,
+        out: &mut dyn Write,
+        resp: &Response,
+        language: Option<&str>,
+    ) -> Result<()> {
+        let conf = self.render_conf(language);
+        let short_conf = RenderConf {
+            display_mode: DisplayMode::Ascii,
+            ..self.render_conf(language)
+        };
+
+        let mut base = StyledText::new();
+        base.set_color(conf.base_style)?;
+        let base_style = base.style();
+
+        let mut blocks = Vec::new();
+        for s in &self.display_config.segments {
+            let mut full = StyledText::new();
+            self.render_line(&mut full, &conf, std::slice::from_ref(s), resp)?;
+            if full.is_empty() {
+                continue;
+            }
+
+            // icons have no ASCII version
+            let short_text = if let Segment::WeatherIcon(_) = s {
+                String::new()
+            } else {
+                let mut short = StyledText::new();
+                self.render_line(&mut short, &short_conf, std::slice::from_ref(s), resp)?;
+                short
+                    .plain()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+
+            // the colors of the segment itself are those of the whole block
+            let visible = || full.spans().iter().filter(|s| !s.text.trim().is_empty());
+            let style = visible()
+                .find(|s| &s.style != base_style)
+                .or_else(|| visible().next())
+                .map(|s| &s.style);
+
+            let mut block = json!({
+                "name": s.name(),
+                "full_text": full.plain().trim(),
+                "short_text": short_text,
+            });
+            if let Some(fg) = style.and_then(|s| s.fg()) {
+                block["color"] = hex(fg).into();
+            }
+            if let Some(bg) = style.and_then(|s| s.bg()) {
+                block["background"] = hex(bg).into();
+            }
+            blocks.push(block);
+        }
+
+        serde_json::to_writer(&mut *out, &blocks)?;
+        writeln!(out, ",")?;
+
+        Ok(())
+    }
+
+    /// Writes the segments with polybar/lemonbar formatting tags, wrapping segments in
+    /// the click actions configured for them.
+    pub fn render_polybar(
+        &mut self,
+        out: &mut dyn Write,
+        resp: &Response,
+        language: Option<&str>,
+    ) -> Result<()> {
+        let conf = self.render_conf(language);
+
+        let mut separator = StyledText::new();
+        separator.set_color(conf.base_style)?;
+        write!(separator, "{}", self.display_config.separator)?;
+        let separator = separator.to_polybar();
+
+        let mut line = Vec::new();
+        for s in &self.display_config.segments {
+            let mut text = StyledText::new();
+            self.render_line(&mut text, &conf, std::slice::from_ref(s), resp)?;
+            if text.is_empty() {
+                continue;
+            }
+
+            let text = text.to_polybar();
+            line.push(match self.display_config.click_actions.get(s.name()) {
+                // unescaped colons would end the command of the action
+                Some(cmd) => format!("%{{A1:{}:}}{}%{{A}}", cmd.replace(':', "\\:"), text),
+                None => text,
+            });
+        }
+
+        writeln!(out, "{}", line.join(&separator))?;
+
+        Ok(())
+    }
+
+    /// Writes the segments with tmux style strings.
+    pub fn render_tmux(
+        &mut self,
+        out: &mut dyn Write,
+        resp: &Response,
+        language: Option<&str>,
+    ) -> Result<()> {
+        let conf = self.render_conf(language);
+
+        let mut text = StyledText::new();
+        self.render_line(&mut text, &conf, &self.display_config.segments, resp)?;
+        writeln!(out, "{}", text.to_tmux())?;
+
+        Ok(())
+    }
+
+    /// Writes the segments with escape codes that don't break the line editing of the
+    /// given shell, for use in its prompt.
+    pub fn render_prompt(
+        &mut self,
+        out: &mut dyn Write,
+        resp: &Response,
+        language: Option<&str>,
+        shell: Shell,
+    ) -> Result<()> {
+        let conf = self.render_conf(language);
+
+        let mut prompt = PromptWriter::new(out, shell);
+        self.render_line(&mut prompt, &conf, &self.display_config.segments, resp)?;
+        prompt.flush()?;
+
+        Ok(())
+    }
+
+    /// Writes text that isn't part of a segment (e.g. the name of a location) in the base
+    /// style, with the same markup and escaping as the segments.
+    pub fn render_text(&self, out: &mut dyn WriteColor, text: &str) -> Result<()> {
+        let base_style = &self.display_config.base_style;
+
+        let mut styled = StyledText::new();
+        styled.set_color(base_style)?;
+        write!(styled, "{}", text)?;
+
+        match self.display_config.output {
+            OutputFormat::Waybar => write!(out, "{}", styled.to_pango())?,
+            OutputFormat::Polybar => write!(out, "{}", styled.to_polybar())?,
+            OutputFormat::Tmux => write!(out, "{}", styled.to_tmux())?,
+            OutputFormat::Bash | OutputFormat::Zsh | OutputFormat::Fish => {
+                let shell = match self.display_config.output {
+                    OutputFormat::Bash => Shell::Bash,
+                    OutputFormat::Zsh => Shell::Zsh,
+                    _ => Shell::Fish,
+                };
+                let mut prompt = PromptWriter::new(&mut *out, shell);
+                prompt.set_color(base_style)?;
+                write!(prompt, "{}", text)?;
+                prompt.reset()?;
+            }
+            OutputFormat::Terminal | OutputFormat::Json | OutputFormat::I3bar => {
+                out.set_color(base_style)?;
+                write!(out, "{}", text)?;
+                out.reset()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_line(
+        &self,
+        out: &mut dyn WriteColor,
+        conf: &RenderConf,
+        segments: &[Segment],
+        resp: &Response,
+    ) -> Result<()> {
+        let base_style = &self.display_config.base_style;
+        out.set_color(base_style)?;
+
+        let mut status = RenderStatus::Empty;
+        for (i, s) in segments.iter().enumerate() {
+            out.set_color(base_style)?;
+            if i > 0 {
+                if let RenderStatus::Rendered = status {
+                    write!(out, "{}", self.display_config.separator)?;
+                }
+            }
+            status = s.render(out, conf, resp)?;
+        }
+
+        out.reset()?;
+
+        Ok(())
+    }
+
+    /// Renders the segments into a string, with ANSI escape codes if `colored` is true.
+    pub fn render_to_string(
+        &mut self,
+        resp: &Response,
+        language: Option<&str>,
+        colored: bool,
+    ) -> Result<String> {
+        let mut buffer = if colored {
+            Buffer::ansi()
+        } else {
+            Buffer::no_color()
+        };
+        self.render(&mut buffer, resp, language)?;
+
+        String::from_utf8(buffer.into_inner()).context("rendered text isn't valid UTF-8")
+    }
+
+    /// Writes the values displayed by each segment as a JSON document.
+    pub fn render_json(
+        &mut self,
+        out: &mut dyn Write,
+        resp: &Response,
+        language: Option<&str>,
+    ) -> Result<()> {
+        let conf = self.render_conf(language);
+
+        let segments = self
+            .display_config
+            .segments
+            .iter()
+            .map(|s| s.to_json(&conf, resp))
+            .collect::<Result<Vec<_>>>()?;
+
+        let doc = json!({
+            "units": conf.units,
+            "segments": segments,
+        });
+        // a single line, for programs reading one update per line
+        serde_json::to_writer(&mut *out, &doc)?;
+        writeln!(out)?;
+
+        Ok(())
+    }
+
+    fn render_conf(&self, language: Option<&str>) -> RenderConf<'_> {
         let env_locale = std::env::var("LANG").ok();
         // clippy 1.57 wrongly warns about this, see https://github.com/rust-lang/rust-clippy/pull/7639#issuecomment-1050340564
         // and the corresponding PR for context. Can be remove when MSRV is bumped.
@@ -78,55 +387,72 @@ impl Renderer {
             })
             .unwrap_or_else(|| "en_US".try_into().unwrap());
 
-        let conf = RenderConf {
+        RenderConf {
             base_style: &self.display_config.base_style,
             display_mode: self.display_config.display_mode,
             locale,
             units: self.display_config.units,
-        };
-
-        let mut status = self.display_config.segments[0].render(out, &conf, resp)?;
-        for s in self.display_config.segments[1..].iter() {
-            out.set_color(&self.display_config.base_style)?;
-            if let RenderStatus::Rendered = status {
-                write!(out, "{}", self.display_config.separator)?;
-            }
-            status = s.render(out, &conf, resp)?;
         }
+    }
 
-        out.reset()?;
+    pub fn display_kinds(&self) -> Result<Vec<QueryKind>> {
+        // the class of Waybar modules comes from the current weather
+        let waybar = self.display_config.output == OutputFormat::Waybar;
 
-        Ok(())
+        Ok(segment_kinds(&self.display_config.segments, waybar))
     }
 
-    pub fn display_kinds(&self) -> Result<Vec<QueryKind>> {
-        let mut current = false;
-        let mut forecast = false;
-        let mut pollution = false;
-        for s in &self.display_config.segments {
-            if s.is_forecast() {
-                forecast = true;
-            } else if s.is_pollution() {
-                pollution = true;
-            } else {
-                current = true;
-            }
+    /// The kinds of weather data only needed by the tooltip.
+    ///
+    /// The tooltip is still shown without them if they can't be queried.
+    pub fn tooltip_kinds(&self) -> Result<Vec<QueryKind>> {
+        if self.display_config.output != OutputFormat::Waybar {
+            return Ok(Vec::new());
         }
 
-        let mut kinds = Vec::new();
+        let kinds = self.display_kinds()?;
+        Ok(segment_kinds(&self.display_config.tooltip, true)
+            .into_iter()
+            .filter(|k| !kinds.contains(k))
+            .collect())
+    }
+}
 
-        if current {
-            kinds.push(QueryKind::Current)
-        }
-        if forecast {
-            kinds.push(QueryKind::ForeCast)
-        }
-        if pollution {
-            kinds.push(QueryKind::Pollution)
+/// The kinds of weather data needed to display the segments, plus the current weather
+/// if `current` is true.
+fn segment_kinds(segments: &[Segment], mut current: bool) -> Vec<QueryKind> {
+    let mut forecast = false;
+    let mut pollution = false;
+    let mut location_name = false;
+
+    for s in segments {
+        if s.is_forecast() {
+            forecast = true;
+        } else if s.is_pollution() {
+            pollution = true;
+        } else if let Segment::LocationName(_) = s {
+            location_name = true;
+        } else {
+            current = true;
         }
+    }
+
+    let mut kinds = Vec::new();
 
-        Ok(kinds)
+    if current {
+        kinds.push(QueryKind::Current)
+    }
+    if forecast {
+        kinds.push(QueryKind::ForeCast)
+    }
+    if pollution {
+        kinds.push(QueryKind::Pollution)
     }
+    if location_name && !current {
+        kinds.push(QueryKind::LocationName)
+    }
+
+    kinds
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -148,12 +474,13 @@ pub enum Segment {
     Alerts(Alerts),
     DayTime(DayTime),
     Pollution(Pollution),
+    Overview(Overview),
 }
 
 impl Segment {
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
@@ -174,19 +501,258 @@ impl Segment {
             Segment::Alerts(c) => c.render(out, conf, resp),
             Segment::DayTime(c) => c.render(out, conf, resp),
             Segment::Pollution(p) => p.render(out, conf, resp),
+            Segment::Overview(o) => o.render(out, conf, resp),
         }
     }
 
     pub fn is_forecast(&self) -> bool {
         matches!(
             self,
-            Segment::DailyForecast(_) | Segment::HourlyForecast(_) | Segment::Alerts(_)
+            Segment::DailyForecast(_)
+                | Segment::HourlyForecast(_)
+                | Segment::Alerts(_)
+                | Segment::Overview(_)
         )
     }
 
     pub fn is_pollution(&self) -> bool {
         matches!(self, Segment::Pollution(_))
     }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Segment::Instant(_) => "instant",
+            Segment::LocationName(_) => "location_name",
+            Segment::Temperature(_) => "temperature",
+            Segment::WeatherIcon(_) => "weather_icon",
+            Segment::WeatherDescription(_) => "weather_description",
+            Segment::WindSpeed(_) => "wind_speed",
+            Segment::Humidity(_) => "humidity",
+            Segment::Rain(_) => "rain",
+            Segment::Snow(_) => "snow",
+            Segment::Pressure(_) => "pressure",
+            Segment::CloudCover(_) => "cloud_cover",
+            Segment::DailyForecast(_) => "daily_forecast",
+            Segment::HourlyForecast(_) => "hourly_forecast",
+            Segment::Alerts(_) => "alerts",
+            Segment::DayTime(_) => "daytime",
+            Segment::Pollution(_) => "pollution",
+            Segment::Overview(_) => "overview",
+        }
+    }
+
+    /// The values displayed by the segment, in the units they are displayed in.
+    ///
+    /// Each segment always has the same keys, which are `null` when there is no data.
+    fn to_json(&self, conf: &RenderConf, resp: &Response) -> Result<serde_json::Value> {
+        let units = conf.units;
+        let mut value = match self {
+            Segment::Instant(i) => {
+                let resp = resp.as_current()?;
+                let date = FixedOffset::east(resp.timezone).timestamp(resp.dt, 0);
+                json!({
+                    "time": resp.dt,
+                    "utc_offset": resp.timezone,
+                    "formatted": date.format(&i.date_format).to_string(),
+                })
+            }
+            Segment::LocationName(_) => json!({ "name": resp.location_name()? }),
+            Segment::Temperature(t) => {
+                let main = &resp.as_current()?.main;
+                let display_mode = t.display_mode.unwrap_or(conf.display_mode);
+                json!({
+                    "icon": icon(display_mode, TEMPERATURE_ICONS),
+                    "temp": round(main.temp, 1),
+                    "feels_like": round(main.feels_like, 1),
+                    "min": round(main.temp_min, 1),
+                    "max": round(main.temp_max, 1),
+                    "unit": temp_unit(units),
+                })
+            }
+            Segment::WeatherIcon(_) => {
+                let resp = resp.as_current()?;
+                let id = resp.weather[0].id;
+                let night = is_night(resp.dt, resp.sys.sunrise, resp.sys.sunset);
+                json!({
+                    "id": id,
+                    "night": night,
+                    "icon": weather_icon(conf.display_mode, units, night, resp.wind.as_ref(), id),
+                })
+            }
+            Segment::WeatherDescription(_) => {
+                json!({ "description": resp.as_current()?.weather[0].description })
+            }
+            Segment::WindSpeed(_) => {
+                let wind = resp.as_current()?.wind.as_ref();
+                let icon = wind
+                    .and_then(|w| wind_direction_icon(conf.display_mode, w.deg))
+                    .map(|i| match conf.display_mode {
+                        DisplayMode::Unicode => format!("{}\u{fe0f}", i),
+                        _ => i.trim().to_owned(),
+                    });
+                json!({
+                    "icon": icon,
+                    "speed": wind.map(|w| round(display_speed(w.speed, units), 1)),
+                    "gust": wind.and_then(|w| w.gale).map(|g| round(display_speed(g, units), 1)),
+                    "direction": wind.and_then(|w| w.deg),
+                    "unit": speed_unit(units),
+                })
+            }
+            Segment::Humidity(_) => json!({
+                "icon": icon(conf.display_mode, HUMIDITY_ICONS),
+                "humidity": resp.as_current()?.main.humidity,
+                "unit": "%",
+            }),
+            Segment::Rain(_) => json!({
+                "icon": icon(conf.display_mode, RAIN_ICONS),
+                "precipitation": resp.as_current()?.rain.as_ref().and_then(|r| r.one_h.or(r.three_h)).map(|p| round(p, 1)),
+                "unit": "mm/h",
+            }),
+            Segment::Snow(_) => json!({
+                "icon": icon(conf.display_mode, SNOW_ICONS),
+                "precipitation": resp.as_current()?.snow.as_ref().and_then(|r| r.one_h.or(r.three_h)).map(|p| round(p, 1)),
+                "unit": "mm/h",
+            }),
+            Segment::Pressure(_) => json!({
+                "icon": icon(conf.display_mode, PRESSURE_ICONS),
+                "pressure": resp.as_current()?.main.pressure,
+                "unit": "hPa",
+            }),
+            Segment::CloudCover(_) => json!({
+                "icon": icon(conf.display_mode, CLOUD_COVER_ICONS),
+                "cloud_cover": resp.as_current()?.clouds.as_ref().map(|c| c.all),
+                "unit": "%",
+            }),
+            Segment::DailyForecast(d) => {
+                let resp = resp.as_forecast()?;
+                let daily = resp.daily.as_deref().unwrap_or_default();
+                let days: Vec<_> = daily
+                    .iter()
+                    .skip(1)
+                    .take(d.days as usize)
+                    .map(|day| {
+                        let (temp, min, max) = match &day.temp {
+                            crate::api::one_call::Temperature::Values(t) => {
+                                (Some(t.day), Some(t.min), Some(t.max))
+                            }
+                            crate::api::one_call::Temperature::Value(t) => (Some(*t), None, None),
+                        };
+                        let wind = Wind {
+                            speed: day.wind_speed,
+                            deg: day.wind_deg,
+                            gale: day.wind_gust,
+                        };
+                        let id = day.weather[0].id;
+                        json!({
+                            "time": day.dt,
+                            "temp": temp.map(|t| round(t, 1)),
+                            "min": min.map(|t| round(t, 1)),
+                            "max": max.map(|t| round(t, 1)),
+                            "id": id,
+                            "icon": weather_icon(conf.display_mode, units, false, Some(&wind), id),
+                            "description": day.weather[0].description,
+                            "summary": day.summary,
+                        })
+                    })
+                    .collect();
+                json!({ "days": days, "unit": temp_unit(units) })
+            }
+            Segment::HourlyForecast(h) => {
+                let resp = resp.as_forecast()?;
+                let hourly = resp.hourly.as_deref().unwrap_or_default();
+                let timezone = FixedOffset::east(resp.timezone_offset);
+                let hours: Vec<_> = hourly
+                    .iter()
+                    .skip(1)
+                    .step_by(1.max(h.step as usize))
+                    .take(h.hours as usize)
+                    .map(|hour| {
+                        let temp = match hour.temp {
+                            crate::api::one_call::Temperature::Value(t) => Some(t),
+                            crate::api::one_call::Temperature::Values(_) => None,
+                        };
+                        let wind = Wind {
+                            speed: hour.wind_speed,
+                            deg: hour.wind_deg,
+                            gale: hour.wind_gust,
+                        };
+                        let id = hour.weather[0].id;
+                        let night = hour_is_night(resp, timezone.timestamp(hour.dt, 0));
+                        json!({
+                            "time": hour.dt,
+                            "temp": temp.map(|t| round(t, 1)),
+                            "id": id,
+                            "night": night,
+                            "icon": weather_icon(conf.display_mode, units, night, Some(&wind), id),
+                            "pop": hour.pop.map(|p| round(p, 2)),
+                        })
+                    })
+                    .collect();
+                json!({ "hours": hours, "unit": temp_unit(units) })
+            }
+            Segment::Alerts(_) => {
+                let resp = resp.as_forecast()?;
+                let alerts: Vec<_> = resp
+                    .alerts
+                    .as_deref()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|a| {
+                        json!({
+                            "event": a.event,
+                            "sender": a.sender_name,
+                            "start": a.start,
+                            "end": a.end,
+                            "description": a.description,
+                            "tags": a.tags,
+                            "severity": a.severity,
+                            "urgency": a.urgency,
+                        })
+                    })
+                    .collect();
+                json!({ "alerts": alerts })
+            }
+            Segment::DayTime(_) => {
+                let resp = resp.as_current()?;
+                json!({
+                    "sunrise": resp.sys.sunrise,
+                    "sunset": resp.sys.sunset,
+                    "sunrise_icon": icon(conf.display_mode, SUNRISE_ICONS),
+                    "sunset_icon": icon(conf.display_mode, SUNSET_ICONS),
+                })
+            }
+            Segment::Pollution(_) => {
+                let p = resp.as_pollution()?.list.first();
+                let icon = match conf.display_mode {
+                    DisplayMode::NerdFonts => p.map(|p| aqi_icon(p.main.aqi.clamp(1, 5))),
+                    _ => None,
+                };
+                json!({
+                    "aqi": p.map(|p| p.main.aqi),
+                    "icon": icon,
+                    "components": p.map(|p| &p.components),
+                })
+            }
+            Segment::Overview(_) => {
+                json!({ "overview": overview(resp.as_forecast()?) })
+            }
+        };
+
+        if let Some(o) = value.as_object_mut() {
+            o.insert("segment".to_owned(), self.name().into());
+        }
+
+        Ok(value)
+    }
+}
+
+/// Rounds a value to the given number of decimals, as it is displayed in the terminal.
+///
+/// Values are stored as `f32`, which serde_json would widen to `f64` as is, turning `15.1`
+/// into `15.100000381469727`.
+fn round(value: f32, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value as f64 * factor).round() / factor
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -213,7 +779,7 @@ impl Instant {
 
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         _conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
@@ -247,11 +813,11 @@ impl LocationName {
 
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         _conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
-        let name = &resp.as_current()?.name;
+        let name = resp.location_name()?;
 
         if let Some(ref style) = self.style {
             out.set_color(style)?;
@@ -296,7 +862,7 @@ impl Default for ScaledColor {
 impl Temperature {
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
@@ -325,7 +891,7 @@ impl Temperature {
                 write!(out, ")")?;
             }
         } else {
-            display_print!(out, display_mode, "\u{e350} ", "\u{1f321}\u{fe0f} ", "T ");
+            write!(out, "{} ", icon(display_mode, TEMPERATURE_ICONS))?;
             display_temp(&self.style, out, temp, conf.base_style, conf.units)?;
         }
         if self.feels_like {
@@ -348,7 +914,7 @@ pub struct WeatherIcon {
 
 impl WeatherIcon {
     fn render_icon(
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         display_mode: DisplayMode,
         units: UnitMode,
         style: &Option<ColorSpec>,
@@ -360,34 +926,18 @@ impl WeatherIcon {
             out.set_color(style)?;
         }
 
-        display_print!(
-            out,
-            display_mode,
-            {
-                let wind_type = wind.map_or(WindType::Low, |w| {
-                    let speed = match units {
-                        UnitMode::Metric => w.speed * 3.6,
-                        _ => w.speed,
-                    };
-                    get_wind_type(speed, units)
-                });
-
-                get_icon(id, night, &wind_type)
-            },
-            format!("{}\u{fe0f}", get_unicode(id, night)),
-            { "" }
-        );
-
-        if let DisplayMode::Ascii = display_mode {
-            Ok(RenderStatus::Empty)
-        } else {
-            Ok(RenderStatus::Rendered)
+        match weather_icon(display_mode, units, night, wind, id) {
+            Some(icon) => {
+                write!(out, "{}", icon)?;
+                Ok(RenderStatus::Rendered)
+            }
+            None => Ok(RenderStatus::Empty),
         }
     }
 
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
@@ -401,8 +951,7 @@ impl WeatherIcon {
             warn!("no weather icon to display in ascii mode!");
         }
 
-        let now = Utc.timestamp(resp.dt, 0);
-        let night = now >= Utc.timestamp(sunset, 0) || now <= Utc.timestamp(sunrise, 0);
+        let night = is_night(resp.dt, sunrise, sunset);
 
         WeatherIcon::render_icon(
             out,
@@ -416,6 +965,131 @@ impl WeatherIcon {
     }
 }
 
+/// The icons of a segment in the Nerd Fonts, Unicode and ASCII display modes.
+type Icons = [&'static str; 3];
+
+const TEMPERATURE_ICONS: Icons = ["\u{e350}", "\u{1f321}\u{fe0f}", "T"];
+const HUMIDITY_ICONS: Icons = ["\u{e373}", "H", "H"];
+const RAIN_ICONS: Icons = ["\u{e371}", "\u{2614}", "R"];
+const SNOW_ICONS: Icons = ["\u{f2dc}", "\u{2744}\u{fe0f}", "S"];
+const PRESSURE_ICONS: Icons = ["\u{e372}", "P", "P"];
+const CLOUD_COVER_ICONS: Icons = ["\u{e33d}", "\u{2601}\u{fe0f}", "C"];
+const SUNRISE_ICONS: Icons = ["\u{e34c}", "\u{2600}\u{fe0f} \u{2b06}\u{fe0f}", "S"];
+const SUNSET_ICONS: Icons = ["\u{e34d}", "\u{2b07}\u{fe0f}", "->"];
+
+fn icon(display_mode: DisplayMode, icons: Icons) -> &'static str {
+    match display_mode {
+        DisplayMode::NerdFonts => icons[0],
+        DisplayMode::Unicode => icons[1],
+        DisplayMode::Ascii => icons[2],
+    }
+}
+
+/// The arrow for the direction the wind is blowing from, if it is known.
+fn wind_direction_icon(display_mode: DisplayMode, deg: Option<f32>) -> Option<&'static str> {
+    let icons = match display_mode {
+        DisplayMode::Ascii => WIND_DIR_ASCII,
+        DisplayMode::Unicode => WIND_DIR_UNICODE,
+        DisplayMode::NerdFonts => WIND_DIR_ICONS,
+    };
+
+    deg.map(|deg| {
+        let dir_idx = ((deg + 22.5) / 45f32).floor() as usize;
+        &icons[3 * dir_idx..3 * dir_idx + 3]
+    })
+}
+
+/// The Nerd Fonts icon for an air quality index between 1 and 5.
+fn aqi_icon(aqi: u16) -> &'static str {
+    match aqi {
+        1 => "\u{f8a3}",
+        2 => "\u{f8a6}",
+        3 => "\u{f8a9}",
+        4 => "\u{f8ac}",
+        _ => "\u{f8af}",
+    }
+}
+
+/// The icon for a weather condition, if there is one in this display mode.
+fn weather_icon(
+    display_mode: DisplayMode,
+    units: UnitMode,
+    night: bool,
+    wind: Option<&Wind>,
+    id: u16,
+) -> Option<String> {
+    match display_mode {
+        DisplayMode::NerdFonts => {
+            let wind_type = wind.map_or(WindType::Low, |w| {
+                get_wind_type(display_speed(w.speed, units), units)
+            });
+
+            Some(get_icon(id, night, &wind_type).to_owned())
+        }
+        DisplayMode::Unicode => Some(format!("{}\u{fe0f}", get_unicode(id, night))),
+        DisplayMode::Ascii => None,
+    }
+}
+
+/// Whether it is night at `dt`; without sunrise and sunset times, it is assumed to be day.
+fn is_night(dt: i64, sunrise: Option<i64>, sunset: Option<i64>) -> bool {
+    match (sunrise, sunset) {
+        (Some(sunrise), Some(sunset)) => {
+            let now = Utc.timestamp(dt, 0);
+            now >= Utc.timestamp(sunset, 0) || now <= Utc.timestamp(sunrise, 0)
+        }
+        _ => false,
+    }
+}
+
+/// Whether an hour of the forecast is at night, using the current day's sunrise and sunset.
+fn hour_is_night(resp: &OneCallResponse, instant: DateTime<FixedOffset>) -> bool {
+    let timezone = FixedOffset::east(resp.timezone_offset);
+    let current_instant = Utc.timestamp(resp.current.dt, 0);
+
+    if instant.day() != current_instant.day() {
+        // use current-day sunrise/sunset to estimate if time tomorrow is day/night;
+        // it's close enough not to matter, considering we are using 1h increments
+        if let (Some(sunset), Some(sunrise)) = (resp.current.sunset, resp.current.sunrise) {
+            let sunrise_dt = timezone.timestamp(sunrise, 0).time();
+            let sunset_dt = timezone.timestamp(sunset, 0).time();
+            let date = instant.date();
+            instant >= date.and_time(sunset_dt).unwrap_or(instant)
+                || instant <= date.and_time(sunrise_dt).unwrap_or(instant)
+        } else {
+            false
+        }
+    } else if let (Some(sunset), Some(sunrise)) = (resp.current.sunset, resp.current.sunrise) {
+        instant >= Utc.timestamp(sunset, 0) || instant <= Utc.timestamp(sunrise, 0)
+    } else {
+        false
+    }
+}
+
+/// Speeds are given in m/s for metric units, but displayed in km/h.
+fn display_speed(speed: f32, units: UnitMode) -> f32 {
+    match units {
+        UnitMode::Metric => speed * 3.6,
+        _ => speed,
+    }
+}
+
+fn speed_unit(units: UnitMode) -> &'static str {
+    match units {
+        UnitMode::Standard => "m/s",
+        UnitMode::Metric => "km/h",
+        UnitMode::Imperial => "mph",
+    }
+}
+
+fn temp_unit(units: UnitMode) -> &'static str {
+    match units {
+        UnitMode::Standard => "K",
+        UnitMode::Metric => "°C",
+        UnitMode::Imperial => "°F",
+    }
+}
+
 fn get_wind_type(speed: f32, units: UnitMode) -> WindType {
     match units {
         UnitMode::Standard => {
@@ -458,7 +1132,7 @@ pub struct WeatherDescription {
 impl WeatherDescription {
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         _conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
@@ -504,33 +1178,23 @@ pub struct WindSpeed {
 impl WindSpeed {
     fn display_wind(
         &self,
-        stdout: &mut StandardStream,
+        stdout: &mut dyn WriteColor,
         wind: &Wind,
         conf: &RenderConf,
     ) -> Result<()> {
-        let (icons, fallback) = match conf.display_mode {
-            DisplayMode::Ascii => (WIND_DIR_ASCII, ""),
-            DisplayMode::Unicode => (WIND_DIR_UNICODE, ""),
-            DisplayMode::NerdFonts => (WIND_DIR_ICONS, "\u{e3a9}"),
+        let fallback = match conf.display_mode {
+            DisplayMode::NerdFonts => "\u{e3a9}",
+            _ => "",
         };
 
-        let icon = wind
-            .deg
-            .map(|deg| {
-                let dir_idx = ((deg + 22.5) / 45f32).floor() as usize;
-                &icons[3 * dir_idx..3 * dir_idx + 3]
-            })
-            .unwrap_or(fallback);
+        let icon = wind_direction_icon(conf.display_mode, wind.deg).unwrap_or(fallback);
         if let DisplayMode::Unicode = conf.display_mode {
             write!(stdout, "{}\u{fe0f}", icon)?;
         } else {
             write!(stdout, "{}", icon)?;
         }
 
-        let speed = match conf.units {
-            UnitMode::Metric => wind.speed * 3.6,
-            _ => wind.speed,
-        };
+        let speed = display_speed(wind.speed, conf.units);
 
         if let WindType::High = get_wind_type(speed, conf.units) {
             display_print!(stdout, conf.display_mode, "\u{e34b} ", " \u{1f32c} ", "");
@@ -538,7 +1202,7 @@ impl WindSpeed {
 
         match &self.style {
             ScaledColor::Scaled => {
-                let speed_color_idx = (speed.floor() as usize).min(WIND_COLORS.len() - 1).max(0);
+                let speed_color_idx = (speed.floor() as usize).min(WIND_COLORS.len() - 1);
                 let mut tmp_style = conf.base_style.clone();
                 stdout.set_color(
                     tmp_style.set_fg(Some(Color::Ansi256(WIND_COLORS[speed_color_idx]))),
@@ -551,19 +1215,14 @@ impl WindSpeed {
         };
         write!(stdout, " {:.1}", speed)?;
         stdout.set_color(conf.base_style)?;
-        let unit = match conf.units {
-            UnitMode::Standard => "m/s",
-            UnitMode::Metric => "km/h",
-            UnitMode::Imperial => "mph",
-        };
-        write!(stdout, " {}", unit)?;
+        write!(stdout, " {}", speed_unit(conf.units))?;
 
         Ok(())
     }
 
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
@@ -590,12 +1249,12 @@ pub struct Humidity {
 impl Humidity {
     fn display_humidity(
         &self,
-        stdout: &mut StandardStream,
+        stdout: &mut dyn WriteColor,
         humidity: u8,
         base_style: &ColorSpec,
         display_mode: DisplayMode,
     ) -> Result<()> {
-        display_print!(stdout, display_mode, "\u{e373}", "H", "H");
+        write!(stdout, "{}", icon(display_mode, HUMIDITY_ICONS))?;
 
         match &self.style {
             ScaledColor::Scaled => {
@@ -618,15 +1277,20 @@ impl Humidity {
 
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
-        let humidity = resp.as_current()?.main.humidity;
-
-        self.display_humidity(out, humidity, conf.base_style, conf.display_mode)?;
-
-        Ok(RenderStatus::Rendered)
+        match resp.as_current()?.main.humidity {
+            Some(humidity) => {
+                self.display_humidity(out, humidity, conf.base_style, conf.display_mode)?;
+                Ok(RenderStatus::Rendered)
+            }
+            None => {
+                debug!("did not receive humidity data; doing nothing");
+                Ok(RenderStatus::Empty)
+            }
+        }
     }
 }
 
@@ -641,7 +1305,7 @@ pub struct Rain {
 impl Rain {
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
@@ -649,7 +1313,7 @@ impl Rain {
 
         if let Some(r) = rain {
             if let Some(mm) = r.one_h.or(r.three_h) {
-                display_print!(out, conf.display_mode, "\u{e371}", "\u{2614}", "R");
+                write!(out, "{}", icon(conf.display_mode, RAIN_ICONS))?;
                 if let Some(ref style) = self.style {
                     out.set_color(style)?;
                 }
@@ -677,7 +1341,7 @@ pub struct Snow {
 impl Snow {
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
@@ -685,7 +1349,7 @@ impl Snow {
 
         if let Some(r) = snow {
             if let Some(mm) = r.one_h.or(r.three_h) {
-                display_print!(out, conf.display_mode, "\u{f2dc}", "\u{2744}\u{fe0f}", "S");
+                write!(out, "{}", icon(conf.display_mode, SNOW_ICONS))?;
                 if let Some(ref style) = self.style {
                     out.set_color(style)?;
                 }
@@ -713,12 +1377,12 @@ pub struct Pressure {
 impl Pressure {
     fn display_pressure(
         &self,
-        stdout: &mut StandardStream,
+        stdout: &mut dyn WriteColor,
         pressure: u16,
         base_style: &ColorSpec,
         display_mode: DisplayMode,
     ) -> Result<()> {
-        display_print!(stdout, display_mode, "\u{e372}", "P", "P");
+        write!(stdout, "{}", icon(display_mode, PRESSURE_ICONS))?;
 
         if let Some(ref style) = self.style {
             stdout.set_color(style)?;
@@ -732,15 +1396,20 @@ impl Pressure {
 
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
-        let pressure = resp.as_current()?.main.pressure;
-
-        self.display_pressure(out, pressure, conf.base_style, conf.display_mode)?;
-
-        Ok(RenderStatus::Rendered)
+        match resp.as_current()?.main.pressure {
+            Some(pressure) => {
+                self.display_pressure(out, pressure, conf.base_style, conf.display_mode)?;
+                Ok(RenderStatus::Rendered)
+            }
+            None => {
+                debug!("did not receive pressure data; doing nothing");
+                Ok(RenderStatus::Empty)
+            }
+        }
     }
 }
 
@@ -755,12 +1424,12 @@ pub struct CloudCover {
 impl CloudCover {
     fn display_cover(
         &self,
-        stdout: &mut StandardStream,
+        stdout: &mut dyn WriteColor,
         cloud_cover: u16,
         base_style: &ColorSpec,
         display_mode: DisplayMode,
     ) -> Result<()> {
-        display_print!(stdout, display_mode, "\u{e33d}", "\u{2601}\u{fe0f}", "C");
+        write!(stdout, "{}", icon(display_mode, CLOUD_COVER_ICONS))?;
 
         if let Some(ref style) = self.style {
             stdout.set_color(style)?;
@@ -774,17 +1443,22 @@ impl CloudCover {
 
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
         let clouds = resp.as_current()?.clouds.as_ref();
 
-        if let Some(clouds) = clouds {
-            self.display_cover(out, clouds.all, conf.base_style, conf.display_mode)?;
+        match clouds {
+            Some(clouds) => {
+                self.display_cover(out, clouds.all, conf.base_style, conf.display_mode)?;
+                Ok(RenderStatus::Rendered)
+            }
+            None => {
+                debug!("did not receive cloud cover data; doing nothing");
+                Ok(RenderStatus::Empty)
+            }
         }
-
-        Ok(RenderStatus::Rendered)
     }
 }
 
@@ -812,7 +1486,7 @@ impl Default for DailyForecast {
 impl DailyForecast {
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
@@ -824,9 +1498,7 @@ impl DailyForecast {
 
         let end = daily.len().min(1 + self.days as usize);
 
-        for i in 1..end {
-            let day = &daily[i as usize];
-
+        for day in daily.iter().take(end).skip(1) {
             let dt = day.dt;
 
             if let crate::api::one_call::Temperature::Values(ref t) = day.temp {
@@ -893,14 +1565,13 @@ impl Default for HourlyForecast {
 impl HourlyForecast {
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
         let resp = resp.as_forecast()?;
         let hourly = resp.hourly.as_deref().unwrap_or_default();
         let timezone = FixedOffset::east(resp.timezone_offset);
-        let current_instant = Utc.timestamp(resp.current.dt, 0);
         let mut first = true;
         out.set_color(conf.base_style)?;
 
@@ -930,27 +1601,7 @@ impl HourlyForecast {
                     gale: hour.wind_gust,
                 };
 
-                let night = if instant.day() != current_instant.day() {
-                    // use current-day sunrise/sunset to estimate if time tomorrow is day/night;
-                    // it's close enough not to matter, considering we are using 1h increments
-                    if let (Some(sunset), Some(sunrise)) =
-                        (resp.current.sunset, resp.current.sunrise)
-                    {
-                        let sunrise_dt = timezone.timestamp(sunrise, 0).time();
-                        let sunset_dt = timezone.timestamp(sunset, 0).time();
-                        let date = instant.date();
-                        instant >= date.and_time(sunset_dt).unwrap_or(instant)
-                            || instant <= date.and_time(sunrise_dt).unwrap_or(instant)
-                    } else {
-                        false
-                    }
-                } else if let (Some(sunset), Some(sunrise)) =
-                    (resp.current.sunset, resp.current.sunrise)
-                {
-                    instant >= Utc.timestamp(sunset, 0) || instant <= Utc.timestamp(sunrise, 0)
-                } else {
-                    false
-                };
+                let night = hour_is_night(resp, instant);
 
                 WeatherIcon::render_icon(
                     out,
@@ -988,7 +1639,7 @@ pub struct Alerts {
 impl Alerts {
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
@@ -1080,6 +1731,12 @@ impl Alerts {
                 write!(out, "{}", a.event)?;
             }
 
+            match (&a.severity, &a.urgency) {
+                (Some(severity), Some(urgency)) => write!(out, " [{}, {}]", severity, urgency)?,
+                (Some(s), None) | (None, Some(s)) => write!(out, " [{}]", s)?,
+                (None, None) => {}
+            }
+
             if self.description {
                 write!(out, ": {}", a.description.replace('\n', " "))?;
             }
@@ -1142,32 +1799,29 @@ pub struct DayTime {
 impl DayTime {
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
         let resp = resp.as_current()?;
         let timezone = resp.timezone;
-        let sunrise = resp.sys.sunrise;
-        let sunset = resp.sys.sunset;
+        let (sunrise, sunset) = match (resp.sys.sunrise, resp.sys.sunset) {
+            (Some(sunrise), Some(sunset)) => (sunrise, sunset),
+            _ => {
+                debug!("did not receive sunrise and sunset times; doing nothing");
+                return Ok(RenderStatus::Empty);
+            }
+        };
 
-        display_print!(
-            out,
-            conf.display_mode,
-            "\u{e34c}  ",
-            "\u{2600}\u{fe0f} \u{2b06}\u{fe0f} ",
-            "S "
-        );
+        let spacing = match conf.display_mode {
+            DisplayMode::NerdFonts => "  ",
+            _ => " ",
+        };
+        write!(out, "{}{}", icon(conf.display_mode, SUNRISE_ICONS), spacing)?;
         let sunrise_date = FixedOffset::east(timezone).timestamp(sunrise, 0);
         write!(out, "{} ", sunrise_date.format("%R"))?;
 
-        display_print!(
-            out,
-            conf.display_mode,
-            "\u{e34d}  ",
-            "\u{2b07}\u{fe0f} ",
-            "-> "
-        );
+        write!(out, "{}{}", icon(conf.display_mode, SUNSET_ICONS), spacing)?;
         let sunset_date = FixedOffset::east(timezone).timestamp(sunset, 0);
         write!(out, "{}", sunset_date.format("%R"))?;
 
@@ -1175,6 +1829,62 @@ impl DayTime {
     }
 }
 
+#[derive(Clone, Default, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Overview {
+    #[serde(with = "option_color_spec")]
+    pub style: Option<ColorSpec>,
+}
+
+impl Overview {
+    fn render(
+        &self,
+        out: &mut dyn WriteColor,
+        conf: &RenderConf,
+        resp: &Response,
+    ) -> Result<RenderStatus> {
+        let resp = resp.as_forecast()?;
+
+        match overview(resp) {
+            Some(o) => {
+                if let Some(ref style) = self.style {
+                    out.set_color(style)?;
+                    write!(out, "{}", o.replace('\n', " "))?;
+                    out.set_color(conf.base_style)?;
+                } else {
+                    write!(out, "{}", o.replace('\n', " "))?;
+                }
+                Ok(RenderStatus::Rendered)
+            }
+            None => Ok(RenderStatus::Empty),
+        }
+    }
+}
+
+/// A CSS class for the weather condition, from the OpenWeather weather id.
+fn condition_class(id: u16) -> &'static str {
+    match id {
+        UNKNOWN_CONDITION => "unknown",
+        200..=299 => "thunderstorm",
+        300..=399 => "drizzle",
+        500..=599 => "rain",
+        600..=699 => "snow",
+        700..=799 => "fog",
+        800 => "clear",
+        _ => "clouds",
+    }
+}
+
+/// The overview of the day, falling back to the shorter summary of the day.
+fn overview(resp: &OneCallResponse) -> Option<&str> {
+    resp.overview.as_deref().or_else(|| {
+        resp.daily
+            .as_deref()
+            .and_then(|d| d.first())
+            .and_then(|d| d.summary.as_deref())
+    })
+}
+
 const POLLUTION_COLORS: [u8; 5] = [46, 226, 214, 202, 9];
 
 #[derive(Clone, Default, Debug, Deserialize, Serialize)]
@@ -1188,24 +1898,18 @@ pub struct Pollution {
 impl Pollution {
     fn render(
         &self,
-        out: &mut StandardStream,
+        out: &mut dyn WriteColor,
         conf: &RenderConf,
         resp: &Response,
     ) -> Result<RenderStatus> {
         let resp = resp.as_pollution()?;
 
-        if let Some(p) = resp.list.get(0) {
-            let aqi = p.main.aqi.max(1).min(5);
+        if let Some(p) = resp.list.first() {
+            let aqi = p.main.aqi.clamp(1, 5);
 
             match conf.display_mode {
                 DisplayMode::NerdFonts => {
-                    let icon = match aqi {
-                        1 => "\u{f8a3}",
-                        2 => "\u{f8a6}",
-                        3 => "\u{f8a9}",
-                        4 => "\u{f8ac}",
-                        _ => "\u{f8af}",
-                    };
+                    let icon = aqi_icon(aqi);
                     write!(out, "\u{e35d} ")?;
                     out.set_color(
                         conf.base_style
@@ -1254,7 +1958,7 @@ impl Pollution {
 
 fn display_temp(
     color_scale: &ScaledColor,
-    out: &mut StandardStream,
+    out: &mut dyn WriteColor,
     temp: f32,
     base_style: &ColorSpec,
     units: UnitMode,
@@ -1266,7 +1970,7 @@ fn display_temp(
                 UnitMode::Metric => temp,
                 UnitMode::Imperial => (temp - 32f32) * 0.555_555_6,
             };
-            let temp_idx = (c.round() + 16f32).min(56f32).max(0f32) as usize;
+            let temp_idx = (c.round() + 16f32).clamp(0f32, 56f32) as usize;
 
             out.set_color(
                 base_style
@@ -1284,11 +1988,7 @@ fn display_temp(
     write!(out, "{:.1}", temp)?;
     out.set_color(base_style)?;
 
-    match units {
-        UnitMode::Standard => write!(out, " K")?,
-        UnitMode::Metric => write!(out, " °C")?,
-        UnitMode::Imperial => write!(out, " °F")?,
-    };
+    write!(out, " {}", temp_unit(units))?;
 
     Ok(())
 }
@@ -1366,6 +2066,7 @@ fn get_icon(id: u16, night: bool, wind_type: &WindType) -> &'static str {
             WindType::Mid => "\u{e301}",
             WindType::Low => "\u{e302}",
         },
+        (_, UNKNOWN_CONDITION) => "\u{e374}",
         (a, b) => {
             debug!("no icon for (night: {}, code: {}); using fallback", a, b);
             if night {
@@ -1422,6 +2123,7 @@ fn get_unicode(id: u16, night: bool) -> &'static str {
         // clouds >=50%
         (true, 801..=809) => "\u{2601}",
         (false, 802..=809) => "\u{26c5}",
+        (_, UNKNOWN_CONDITION) => "\u{2753}",
         (a, b) => {
             debug!("no unicode for (night: {}, code: {}); using fallback", a, b);
             if night {
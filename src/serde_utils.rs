@@ -212,6 +212,7 @@ pub(crate) mod segment_vec {
                             "alerts" => Segment::Alerts(Alerts::default()),
                             "daytime" => Segment::DayTime(DayTime::default()),
                             "pollution" => Segment::Pollution(Pollution::default()),
+                            "overview" => Segment::Overview(Overview::default()),
                             a => {
                                 return Err(de::Error::unknown_variant(
                                     a,
@@ -230,6 +231,7 @@ pub(crate) mod segment_vec {
                                         "alerts",
                                         "daytime",
                                         "pollution",
+                                        "overview",
                                     ],
                                 ))
                             }
@@ -249,3 +251,38 @@ pub(crate) mod segment_vec {
         d.deserialize_seq(SVisitor)
     }
 }
+
+pub(crate) mod one_or_many {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Inner<T> {
+        None,
+        One(T),
+        Many(Vec<T>),
+    }
+
+    pub(crate) fn deserialize<'de, D, T>(de: D) -> Result<Vec<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        Ok(match Inner::deserialize(de)? {
+            Inner::None => Vec::new(),
+            Inner::One(t) => vec![t],
+            Inner::Many(v) => v,
+        })
+    }
+
+    pub(crate) fn serialize<S, T>(v: &[T], ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize,
+    {
+        match v {
+            [t] => t.serialize(ser),
+            v => v.serialize(ser),
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! A local server sharing weather data between clients, over a Unix socket.
+//!
+//! The protocol is line-based: a client sends the name of a layout on a single line, and
+//! the server answers with `ok` or `error: <message>` on the first line, followed by the
+//! output of the layout.
+
+use crate::{
+    api::Response, config::DisplayConfig, segments::Renderer, Girouette, Location, OutputFormat,
+    UnitMode, WeatherClient,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use log::*;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use termcolor::Buffer;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::Mutex,
+    time::timeout,
+};
+
+/// The socket used when none is configured: `girouette.sock` in the runtime directory
+/// (or the cache directory on systems without one).
+pub fn default_socket_path() -> Result<PathBuf> {
+    let dirs = WeatherClient::directories()
+        .ok_or_else(|| anyhow!("could not find a directory for the socket"))?;
+    let dir = dirs.runtime_dir().unwrap_or_else(|| dirs.cache_dir());
+
+    Ok(dir.join("girouette.sock"))
+}
+
+pub struct Server {
+    lib: Girouette,
+    location: Location,
+    offline: bool,
+    layouts: HashMap<String, DisplayConfig>,
+    refresh: Duration,
+    timeout: Duration,
+    responses: Mutex<HashMap<UnitMode, (Instant, Response)>>,
+}
+
+impl Server {
+    /// Creates a server for the given layouts, querying the weather again once the data
+    /// shared by all clients is older than `refresh`.
+    pub fn new(
+        lib: Girouette,
+        location: Location,
+        offline: bool,
+        layouts: HashMap<String, DisplayConfig>,
+        refresh: Duration,
+        timeout: Duration,
+    ) -> Self {
+        Server {
+            lib,
+            location,
+            offline,
+            layouts,
+            refresh,
+            timeout,
+            responses: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Listens on the socket at `path` until the program is killed.
+    ///
+    /// Each client is handled in its own task, but only one query to the weather service
+    /// is ever running: the others wait for its data.
+    pub async fn serve(self, path: &Path) -> Result<()> {
+        if path.exists() {
+            if UnixStream::connect(path).await.is_ok() {
+                bail!(
+                    "another server is already listening on '{}'",
+                    path.display()
+                );
+            }
+            debug!("removing stale socket '{}'", path.display());
+            std::fs::remove_file(path)
+                .with_context(|| format!("could not remove '{}'", path.display()))?;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("could not listen on '{}'", path.display()))?;
+        info!("listening on '{}'", path.display());
+
+        let server = Arc::new(self);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = Arc::clone(&server);
+            tokio::spawn(async move {
+                if let Err(e) = server.handle(stream).await {
+                    warn!("failed to answer client: {:#}", e);
+                }
+            });
+        }
+    }
+
+    async fn handle(&self, stream: UnixStream) -> Result<()> {
+        let (read, mut write) = stream.into_split();
+
+        let mut name = String::new();
+        timeout(self.timeout, BufReader::new(read).read_line(&mut name))
+            .await
+            .context("client didn't ask for a layout")??;
+        let name = name.trim();
+        if name.is_empty() {
+            // e.g. another server checking whether this one is running
+            return Ok(());
+        }
+        debug!("client asked for layout '{}'", name);
+
+        match self.render(name).await {
+            Ok(output) => {
+                write.write_all(b"ok\n").await?;
+                write.write_all(&output).await?;
+            }
+            Err(e) => {
+                warn!("layout '{}': {:#}", name, e);
+                write
+                    .write_all(format!("error: {:#}\n", e).as_bytes())
+                    .await?;
+            }
+        }
+        write.shutdown().await?;
+
+        Ok(())
+    }
+
+    async fn render(&self, name: &str) -> Result<Vec<u8>> {
+        let layout = self
+            .layouts
+            .get(name)
+            .ok_or_else(|| anyhow!("unknown layout '{}'", name))?;
+
+        let mut responses = self.responses.lock().await;
+        self.refresh(&mut responses, layout.units).await?;
+        let (_, response) = &responses[&layout.units];
+
+        // other outputs have their own markup for styles
+        let mut buffer = if layout.output == OutputFormat::Terminal {
+            Buffer::ansi()
+        } else {
+            Buffer::no_color()
+        };
+        self.lib.render(layout, response, &mut buffer)?;
+
+        Ok(buffer.into_inner())
+    }
+
+    /// Queries the weather in the given units again if needed, for all layouts using them.
+    async fn refresh(
+        &self,
+        responses: &mut HashMap<UnitMode, (Instant, Response)>,
+        units: UnitMode,
+    ) -> Result<()> {
+        if let Some((time, _)) = responses.get(&units) {
+            if time.elapsed() < self.refresh {
+                return Ok(());
+            }
+        }
+
+        let mut kinds = Vec::new();
+        let mut tooltip_kinds = Vec::new();
+        for layout in self.layouts.values().filter(|l| l.units == units) {
+            let renderer = Renderer::from(layout);
+            for kind in renderer.display_kinds()? {
+                if !kinds.contains(&kind) {
+                    kinds.push(kind);
+                }
+            }
+            for kind in renderer.tooltip_kinds()? {
+                if !tooltip_kinds.contains(&kind) {
+                    tooltip_kinds.push(kind);
+                }
+            }
+        }
+        tooltip_kinds.retain(|k| !kinds.contains(k));
+
+        let mut response = self
+            .lib
+            .query(&self.location, self.offline, &kinds, units)
+            .await?;
+        self.lib
+            .query_optional(
+                &mut response,
+                &self.location,
+                self.offline,
+                &tooltip_kinds,
+                units,
+            )
+            .await;
+        responses.insert(units, (Instant::now(), response));
+
+        Ok(())
+    }
+}
+
+/// Asks the server listening on `path` for the output of a layout.
+pub async fn query_server(path: &Path, layout: &str, wait: Duration) -> Result<Vec<u8>> {
+    let stream = UnixStream::connect(path).await.with_context(|| {
+        format!(
+            "could not connect to '{}'; is `girouette --server` running?",
+            path.display()
+        )
+    })?;
+
+    query_stream(stream, layout, wait).await
+}
+
+async fn query_stream(mut stream: UnixStream, layout: &str, wait: Duration) -> Result<Vec<u8>> {
+    stream.write_all(format!("{}\n", layout).as_bytes()).await?;
+
+    let mut answer = Vec::new();
+    timeout(wait, stream.read_to_end(&mut answer))
+        .await
+        .context("the server took too long to answer")??;
+
+    let end = answer
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or_else(|| anyhow!("invalid answer from the server"))?;
+    let status = String::from_utf8_lossy(&answer[..end]).into_owned();
+    match status.strip_prefix("error: ") {
+        Some(e) => bail!("{}", e),
+        None if status == "ok" => Ok(answer.split_off(end + 1)),
+        None => bail!("invalid answer from the server: {}", status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segments::{Segment, Temperature};
+
+    fn server() -> Server {
+        let layout = DisplayConfig {
+            output: OutputFormat::Json,
+            segments: vec![Segment::Temperature(Temperature::default())],
+            ..Default::default()
+        };
+        let lib = Girouette::new(
+            layout.clone(),
+            Vec::new(),
+            HashMap::new(),
+            None,
+            Duration::from_secs(1),
+            None,
+        );
+        let current = serde_json::from_str(
+            r#"{
+                "coord": { "lat": 48.85, "lon": 2.35 },
+                "weather": [{ "id": 800, "main": "Clear", "description": "clear sky", "icon": "01d" }],
+                "main": { "temp": 21.5, "feels_like": 21.0, "temp_min": 18.0, "temp_max": 24.0 },
+                "dt": 1718971200,
+                "sys": { "country": "FR" },
+                "timezone": 7200,
+                "id": 0,
+                "name": "Paris"
+            }"#,
+        )
+        .unwrap();
+
+        let server = Server::new(
+            lib,
+            Location::LatLon(48.85, 2.35),
+            true,
+            HashMap::from([("default".to_owned(), layout)]),
+            Duration::from_secs(3600),
+            Duration::from_secs(1),
+        );
+        // no provider is configured: the weather must come from here
+        server.responses.try_lock().unwrap().insert(
+            UnitMode::Metric,
+            (Instant::now(), Response::from_current(current)),
+        );
+        server
+    }
+
+    async fn ask(server: &Server, layout: &str) -> Result<Vec<u8>> {
+        let (client, stream) = UnixStream::pair().unwrap();
+        let (handled, answer) = tokio::join!(
+            server.handle(stream),
+            query_stream(client, layout, Duration::from_secs(1))
+        );
+        handled.unwrap();
+        answer
+    }
+
+    #[tokio::test]
+    async fn known_layout() {
+        let output = ask(&server(), "default").await.unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.ends_with('\n'), "{}", output);
+        assert_eq!(output.lines().count(), 1, "{}", output);
+        assert!(output.contains("21.5"), "{}", output);
+    }
+
+    #[tokio::test]
+    async fn unknown_layout() {
+        let e = ask(&server(), "nope").await.unwrap_err();
+        assert_eq!(e.to_string(), "unknown layout 'nope'");
+    }
+}